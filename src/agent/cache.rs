@@ -0,0 +1,154 @@
+//! Content-addressed cache of provider replies.
+//!
+//! Each entry is stored as `<key>.json` in the cache directory, where the key is a hash
+//! of the query and of the provider that answers it. Identical queries sent to the same
+//! provider are answered from the cache without calling the provider again.
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+use super::provider::{
+    OutputCallback, Provider, ProviderMetadata, ProviderRequest, ProviderResponse,
+};
+
+/// Name of the cache directory inside the metadata directory.
+pub const CACHE_DIR_NAME: &str = "cache";
+
+const CACHE_ENTRY_EXTENSION: &str = "json";
+
+/// A cached reply.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// The raw reply of the provider.
+    pub reply: String,
+    /// Information about the provider that produced the reply.
+    pub metadata: ProviderMetadata,
+}
+
+/// Summary of the content of a cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of cached replies.
+    pub entries: usize,
+    /// Total size of the cached replies on disk, in bytes.
+    pub bytes: u64,
+}
+
+/// Cache of provider replies, stored in a directory.
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+}
+
+impl ResponseCache {
+    /// Opens the cache stored in `dir`; the directory is created on first store.
+    pub fn open(dir: &Path) -> Self {
+        ResponseCache {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// Returns the key of `query` sent to the provider identified by `provider`.
+    pub fn key(provider: &str, query: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(provider.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(query.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(key).with_extension(CACHE_ENTRY_EXTENSION)
+    }
+
+    /// Returns the entry cached for `key`, if any.
+    ///
+    /// An unreadable entry is treated as missing.
+    pub fn lookup(&self, key: &str) -> Option<CacheEntry> {
+        let content = std::fs::read_to_string(self.entry_path(key)).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                tracing::warn!("Ignoring corrupted cache entry {}: {}", key, e);
+                None
+            }
+        }
+    }
+
+    /// Stores `entry` for `key`.
+    pub fn store(&self, key: &str, entry: &CacheEntry) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create cache '{}'", self.dir.display()))?;
+        let path = self.entry_path(key);
+        std::fs::write(&path, serde_json::to_string_pretty(entry)?)
+            .with_context(|| format!("Failed to write cache entry '{}'", path.display()))
+    }
+
+    fn entries(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let read_dir = match std::fs::read_dir(&self.dir) {
+            Ok(x) => x,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read cache '{}'", self.dir.display()))
+            }
+        };
+        let mut entries = Vec::new();
+        for entry in read_dir {
+            let path = entry?.path();
+            if path.is_file()
+                && path.extension().and_then(|x| x.to_str()) == Some(CACHE_ENTRY_EXTENSION)
+            {
+                entries.push(path);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Returns the number and total size of the cached replies.
+    pub fn stats(&self) -> anyhow::Result<CacheStats> {
+        let mut stats = CacheStats::default();
+        for path in self.entries()? {
+            stats.entries += 1;
+            stats.bytes += std::fs::metadata(&path)?.len();
+        }
+        Ok(stats)
+    }
+
+    /// Removes every cached reply, returning how many were removed.
+    pub fn clear(&self) -> anyhow::Result<usize> {
+        let entries = self.entries()?;
+        for path in &entries {
+            std::fs::remove_file(path)
+                .with_context(|| format!("Failed to remove cache entry '{}'", path.display()))?;
+        }
+        Ok(entries.len())
+    }
+}
+
+/// Provider serving a reply taken from the cache.
+#[derive(Debug, Clone)]
+pub struct CachedProvider {
+    /// The cached reply.
+    pub entry: CacheEntry,
+}
+
+impl Provider for CachedProvider {
+    fn name(&self) -> String {
+        format!("cache ({})", self.entry.metadata.name)
+    }
+
+    fn call(
+        &self,
+        _request: &ProviderRequest,
+        mut on_output: OutputCallback,
+    ) -> anyhow::Result<ProviderResponse> {
+        on_output(&self.entry.reply);
+        Ok(ProviderResponse {
+            text: self.entry.reply.clone(),
+            metadata: self.entry.metadata.clone(),
+            ..Default::default()
+        })
+    }
+}
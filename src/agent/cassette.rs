@@ -0,0 +1,122 @@
+//! Record/replay storage of provider replies.
+//!
+//! A cassette maps the normalized hash of each `@answer` query to the raw reply the
+//! provider gave. In [`CassetteMode::Record`] every completed answer is stored; in
+//! [`CassetteMode::Replay`] answers are served from the cassette without calling any
+//! provider, and a query that was never recorded is an error.
+use anyhow::Context;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::provider::{
+    OutputCallback, Provider, ProviderMetadata, ProviderRequest, ProviderResponse,
+};
+use crate::utils::file::FileAccessor;
+
+/// File name of the cassette inside the metadata directory.
+pub const CASSETTE_FILE_NAME: &str = "cassette.json";
+
+/// How a cassette is used during an execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Store every reply received from providers.
+    Record,
+    /// Serve replies from the cassette instead of calling providers.
+    Replay,
+}
+
+/// Recorded replies, keyed by normalized query hash.
+#[derive(Debug)]
+pub struct Cassette {
+    mode: CassetteMode,
+    path: PathBuf,
+    replies: Mutex<BTreeMap<String, String>>,
+}
+
+impl Cassette {
+    /// Opens the cassette at `path`.
+    ///
+    /// Recording starts from the existing cassette, if any, so that re-recording only
+    /// replaces the replies of queries asked again. Replaying requires the cassette to exist.
+    pub fn open(path: &Path, mode: CassetteMode) -> anyhow::Result<Self> {
+        let replies = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Invalid cassette '{}'", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && mode == CassetteMode::Record => {
+                BTreeMap::new()
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read cassette '{}'", path.display()))
+            }
+        };
+        Ok(Cassette {
+            mode,
+            path: path.to_path_buf(),
+            replies: Mutex::new(replies),
+        })
+    }
+
+    /// Returns how the cassette is used.
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    /// Returns the reply recorded for `query_hash`, if any.
+    pub fn lookup(&self, query_hash: &str) -> Option<String> {
+        self.replies.lock().unwrap().get(query_hash).cloned()
+    }
+
+    /// Stores `raw_reply` for `query_hash` and saves the cassette through `file_access`.
+    pub fn record(
+        &self,
+        query_hash: &str,
+        raw_reply: &str,
+        file_access: &dyn FileAccessor,
+    ) -> anyhow::Result<()> {
+        let mut replies = self.replies.lock().unwrap();
+        replies.insert(query_hash.to_string(), raw_reply.to_string());
+        let serialized = serde_json::to_string_pretty(&*replies)?;
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        file_access
+            .write_file(
+                &self.path,
+                &serialized,
+                Some("Recorded a provider reply into the cassette."),
+            )
+            .with_context(|| format!("Failed to write cassette '{}'", self.path.display()))
+    }
+}
+
+/// Provider serving a reply taken from a cassette.
+#[derive(Debug, Clone)]
+pub struct CassetteProvider {
+    /// The recorded reply.
+    pub reply: String,
+}
+
+impl Provider for CassetteProvider {
+    fn name(&self) -> String {
+        "cassette".to_string()
+    }
+
+    fn call(
+        &self,
+        _request: &ProviderRequest,
+        mut on_output: OutputCallback,
+    ) -> anyhow::Result<ProviderResponse> {
+        on_output(&self.reply);
+        Ok(ProviderResponse {
+            text: self.reply.clone(),
+            metadata: ProviderMetadata {
+                provider: "cassette".to_string(),
+                model: None,
+                name: self.name(),
+            },
+            ..Default::default()
+        })
+    }
+}
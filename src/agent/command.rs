@@ -0,0 +1,120 @@
+//! Parsing of provider command templates into program arguments.
+//!
+//! Templates follow the quoting rules of POSIX shells, on every platform:
+//! - words are separated by unquoted whitespace;
+//! - single quotes preserve their content literally;
+//! - double quotes preserve whitespace, and a backslash in them escapes `"`, `\`, `$` and `` ` ``;
+//! - outside quotes a backslash escapes any character;
+//! - `$NAME` and `${NAME}` are expanded outside single quotes, an unset variable being empty.
+//!
+//! Pipes, redirections and other shell operators are not interpreted: they need `shell: true`.
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Splits `template` into program arguments, expanding variables with `lookup`.
+///
+/// An unquoted word that expands to nothing is dropped, like in a shell.
+///
+/// # Errors
+///
+/// Fails on unterminated quotes or `${`, and on a trailing backslash.
+pub fn parse_command_template<F>(template: &str, lookup: F) -> anyhow::Result<Vec<String>>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut args = Vec::new();
+    let mut word = String::new();
+    // Whether the current word exists even if empty, i.e. it contains quotes
+    let mut quoted = false;
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if !word.is_empty() || quoted {
+                    args.push(std::mem::take(&mut word));
+                }
+                quoted = false;
+            }
+            '\'' => {
+                quoted = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => anyhow::bail!("unterminated single quote in '{}'", template),
+                    }
+                }
+            }
+            '"' => {
+                quoted = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => anyhow::bail!("unterminated double quote in '{}'", template),
+                        },
+                        Some('$') => expand_variable(&mut chars, &mut word, &lookup, template)?,
+                        Some(c) => word.push(c),
+                        None => anyhow::bail!("unterminated double quote in '{}'", template),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.push(c),
+                None => anyhow::bail!("trailing backslash in '{}'", template),
+            },
+            '$' => expand_variable(&mut chars, &mut word, &lookup, template)?,
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() || quoted {
+        args.push(word);
+    }
+    Ok(args)
+}
+
+/// Expands the variable following a `$` into `word`; a `$` not followed by a name is kept.
+fn expand_variable<F>(
+    chars: &mut Peekable<Chars>,
+    word: &mut String,
+    lookup: &F,
+    template: &str,
+) -> anyhow::Result<()>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut name = String::new();
+    if chars.peek() == Some(&'{') {
+        chars.next();
+        loop {
+            match chars.next() {
+                Some('}') => break,
+                Some(c) => name.push(c),
+                None => anyhow::bail!("unterminated '${{' in '{}'", template),
+            }
+        }
+    } else {
+        while let Some(&c) = chars.peek() {
+            let valid = match name.is_empty() {
+                true => c.is_ascii_alphabetic() || c == '_',
+                false => c.is_ascii_alphanumeric() || c == '_',
+            };
+            if !valid {
+                break;
+            }
+            name.push(c);
+            chars.next();
+        }
+        if name.is_empty() {
+            word.push('$');
+            return Ok(());
+        }
+    }
+    word.push_str(&lookup(&name).unwrap_or_default());
+    Ok(())
+}
@@ -0,0 +1,217 @@
+//! Deterministic provider returning scripted replies, for testing contexts offline.
+//!
+//! Replies are described by a list of entries:
+//! - a plain string is returned by the next call in sequence;
+//! - `{ match: "<regex>", reply: "<text>" }` is returned whenever the query matches the regex,
+//!   without consuming the sequence;
+//! - `{ echo: true }` returns the query itself, in sequence.
+//!
+//! When the sequence is exhausted the provider fails, unless `echo: true` is given at the top
+//! level, in which case every further call echoes the query.
+use anyhow::Context;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::provider::{
+    OutputCallback, Provider, ProviderMetadata, ProviderRequest, ProviderResponse,
+};
+use crate::ast2::JsonPlusEntity;
+
+/// Prefix of the string form of the provider, followed by the path of a JSON script.
+pub const MOCK_FILE_PREFIX: &str = "mock:";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum MockEntry {
+    Text(String),
+    Rule {
+        #[serde(rename = "match")]
+        pattern: String,
+        reply: String,
+    },
+    Echo {
+        echo: bool,
+    },
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct MockScript {
+    #[serde(default)]
+    replies: Vec<MockEntry>,
+    #[serde(default)]
+    echo: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum MockScriptForm {
+    Replies(Vec<MockEntry>),
+    Script(MockScript),
+}
+
+/// Reply produced by a sequence entry.
+#[derive(Debug, Clone, PartialEq)]
+enum MockReply {
+    Text(String),
+    Echo,
+}
+
+/// Provider returning scripted replies.
+///
+/// Selected by `provider: "mock:<file>"`, where `<file>` is a JSON script relative to the
+/// project root, or by `provider: { kind: "mock", replies: [...], echo: true }`.
+#[derive(Debug, Clone)]
+pub struct MockProvider {
+    rules: Vec<(Regex, String)>,
+    sequence: Vec<MockReply>,
+    echo: bool,
+    cursor: Arc<AtomicUsize>,
+}
+
+impl MockProvider {
+    /// Builds a provider from a script, with the sequence position kept in `cursor`.
+    fn from_script(script: MockScript, cursor: Arc<AtomicUsize>) -> anyhow::Result<Self> {
+        let mut rules = Vec::new();
+        let mut sequence = Vec::new();
+        for entry in script.replies {
+            match entry {
+                MockEntry::Text(text) => sequence.push(MockReply::Text(text)),
+                MockEntry::Rule { pattern, reply } => {
+                    let regex = Regex::new(&pattern)
+                        .with_context(|| format!("bad mock reply pattern '{}'", pattern))?;
+                    rules.push((regex, reply));
+                }
+                MockEntry::Echo { echo } => {
+                    if echo {
+                        sequence.push(MockReply::Echo);
+                    }
+                }
+            }
+        }
+        Ok(MockProvider {
+            rules,
+            sequence,
+            echo: script.echo,
+            cursor,
+        })
+    }
+
+    /// Reads the script from the value of the `provider` parameter, with script files
+    /// relative to `root`, or to the working directory if not given.
+    fn script_from_json_plus(
+        entity: &JsonPlusEntity,
+        root: Option<&Path>,
+    ) -> anyhow::Result<MockScript> {
+        let form: MockScriptForm = match entity {
+            JsonPlusEntity::Object(x) => match x.get_as_string_only("file") {
+                Some(file) => Self::read_script_file(root, &file)?,
+                None => serde_json::from_value(serde_json::Value::from(x))
+                    .context("bad mock provider script")?,
+            },
+            x => {
+                let spec = x
+                    .as_string_only()
+                    .with_context(|| format!("bad mock provider: {:?}", x))?;
+                let file = spec
+                    .strip_prefix(MOCK_FILE_PREFIX)
+                    .with_context(|| format!("bad mock provider: {}", spec))?;
+                Self::read_script_file(root, file.trim())?
+            }
+        };
+        Ok(match form {
+            MockScriptForm::Replies(replies) => MockScript {
+                replies,
+                echo: false,
+            },
+            MockScriptForm::Script(script) => script,
+        })
+    }
+
+    fn read_script_file(root: Option<&Path>, file: &str) -> anyhow::Result<MockScriptForm> {
+        let path = match root {
+            Some(root) => root.join(file),
+            None => PathBuf::from(file),
+        };
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read mock script '{}'", file))?;
+        serde_json::from_str(&content).with_context(|| format!("bad mock script '{}'", file))
+    }
+
+    fn reply_to(&self, query: &str) -> anyhow::Result<String> {
+        if let Some((_, reply)) = self.rules.iter().find(|(regex, _)| regex.is_match(query)) {
+            return Ok(reply.clone());
+        }
+        let index = self.cursor.fetch_add(1, Ordering::SeqCst);
+        match self.sequence.get(index) {
+            Some(MockReply::Text(text)) => Ok(text.clone()),
+            Some(MockReply::Echo) => Ok(query.to_string()),
+            None if self.echo => Ok(query.to_string()),
+            None => anyhow::bail!(
+                "mock provider ran out of replies after {} calls",
+                self.sequence.len()
+            ),
+        }
+    }
+}
+
+impl Provider for MockProvider {
+    fn name(&self) -> String {
+        "mock".to_string()
+    }
+
+    fn call(
+        &self,
+        request: &ProviderRequest,
+        mut on_output: OutputCallback,
+    ) -> anyhow::Result<ProviderResponse> {
+        let text = self.reply_to(&request.prompt())?;
+        on_output(&text);
+        Ok(ProviderResponse {
+            text,
+            metadata: ProviderMetadata {
+                provider: "mock".to_string(),
+                model: None,
+                name: self.name(),
+            },
+            ..Default::default()
+        })
+    }
+}
+
+/// Builds mock providers, keeping the sequence position of each distinct script.
+///
+/// Every `@answer` using the same script within one execution advances the same sequence,
+/// so the n-th call gets the n-th reply.
+#[derive(Debug, Default)]
+pub struct MockProviderFactory {
+    /// Directory script files are relative to, the working directory if not given
+    root: Option<PathBuf>,
+    cursors: Mutex<HashMap<String, Arc<AtomicUsize>>>,
+}
+
+impl MockProviderFactory {
+    /// Creates a factory reading script files relative to `root`, the project root.
+    pub fn with_root(root: PathBuf) -> Self {
+        MockProviderFactory {
+            root: Some(root),
+            ..Default::default()
+        }
+    }
+
+    /// Builds the provider described by the value of the `provider` parameter.
+    pub fn build(&self, entity: &JsonPlusEntity) -> anyhow::Result<MockProvider> {
+        let script = MockProvider::script_from_json_plus(entity, self.root.as_deref())?;
+        let cursor = self
+            .cursors
+            .lock()
+            .map_err(|_| anyhow::anyhow!("mock provider state poisoned"))?
+            .entry(entity.to_string())
+            .or_default()
+            .clone();
+        MockProvider::from_script(script, cursor)
+    }
+}
@@ -1 +1,32 @@
+pub mod cache;
+pub mod cassette;
+pub mod command;
+pub mod mock;
+pub mod openai;
+pub mod provider;
 pub mod shell;
+pub mod usage;
+
+#[cfg(test)]
+#[path = "tests/test_openai.rs"]
+mod test_openai;
+
+#[cfg(test)]
+#[path = "tests/test_provider.rs"]
+mod test_provider;
+
+#[cfg(test)]
+#[path = "tests/test_mock.rs"]
+mod test_mock;
+
+#[cfg(test)]
+#[path = "tests/test_cache.rs"]
+mod test_cache;
+
+#[cfg(test)]
+#[path = "tests/test_usage.rs"]
+mod test_usage;
+
+#[cfg(test)]
+#[path = "tests/test_command.rs"]
+mod test_command;
@@ -0,0 +1,211 @@
+use anyhow::Context;
+use serde_json::json;
+use std::io::{BufRead, BufReader};
+use std::time::Duration;
+use tracing::{debug, error};
+
+use super::provider::{
+    OutputCallback, Provider, ProviderMetadata, ProviderRequest, ProviderResponse, TransientError,
+};
+use crate::ast2::{JsonPlusEntity, JsonPlusObject};
+use crate::execute2::ChatMessage;
+
+const DEFAULT_API_KEY_ENV: &str = "OPENAI_API_KEY";
+const SSE_DATA_PREFIX: &str = "data:";
+const SSE_DONE_MARKER: &str = "[DONE]";
+
+/// Configuration of an OpenAI-compatible chat completion endpoint.
+///
+/// Built from the object form of the `provider` parameter, e.g.
+/// `provider: { kind: "openai", base_url: "http://localhost:11434/v1", model: "qwen2.5:1.5b" }`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenAiConfig {
+    /// Base url of the API, `/chat/completions` is appended to it.
+    pub base_url: String,
+    /// Model name sent in the request body.
+    pub model: String,
+    /// Name of the environment variable holding the API key, if any.
+    pub api_key_env: String,
+    /// Optional sampling temperature.
+    pub temperature: Option<f64>,
+    /// Optional limit on generated tokens.
+    pub max_tokens: Option<i64>,
+}
+
+impl OpenAiConfig {
+    /// Reads the configuration from the object form of the `provider` parameter.
+    pub fn from_json_plus(object: &JsonPlusObject) -> anyhow::Result<Self> {
+        let base_url = object
+            .get_as_string_only("base_url")
+            .context("OpenAI provider requires a 'base_url' string")?;
+        let model = object
+            .get_as_string_only("model")
+            .context("OpenAI provider requires a 'model' string")?;
+        let api_key_env = object
+            .get_as_string_only("api_key_env")
+            .unwrap_or(DEFAULT_API_KEY_ENV.to_string());
+        let temperature = match object.get("temperature") {
+            Some(JsonPlusEntity::Float(x)) => Some(*x),
+            Some(JsonPlusEntity::Integer(x)) => Some(*x as f64),
+            Some(x) => anyhow::bail!("Unsupported 'temperature' value: {:?}", x),
+            None => None,
+        };
+        let max_tokens = object.get_as_integer_only("max_tokens");
+        Ok(OpenAiConfig {
+            base_url,
+            model,
+            api_key_env,
+            temperature,
+            max_tokens,
+        })
+    }
+
+    fn endpoint(&self) -> String {
+        format!("{}/chat/completions", self.base_url.trim_end_matches('/'))
+    }
+}
+
+/// Provider talking to an OpenAI-compatible chat completion endpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenAiProvider {
+    /// The endpoint configuration.
+    pub config: OpenAiConfig,
+}
+
+impl OpenAiProvider {
+    /// Reads the configuration from the value of the `provider` parameter.
+    pub fn from_json_plus(entity: &JsonPlusEntity) -> anyhow::Result<Self> {
+        let object = entity
+            .as_object()
+            .with_context(|| format!("OpenAI provider must be an object: {:?}", entity))?;
+        Ok(OpenAiProvider {
+            config: OpenAiConfig::from_json_plus(object)?,
+        })
+    }
+}
+
+impl Provider for OpenAiProvider {
+    fn name(&self) -> String {
+        format!("openai: {} @ {}", self.config.model, self.config.base_url)
+    }
+
+    fn call(
+        &self,
+        request: &ProviderRequest,
+        on_output: OutputCallback,
+    ) -> anyhow::Result<ProviderResponse> {
+        let text = openai_call(
+            &self.config,
+            &request.messages(),
+            request.timeout,
+            on_output,
+        )?;
+        Ok(ProviderResponse {
+            text,
+            metadata: ProviderMetadata {
+                provider: "openai".to_string(),
+                model: Some(self.config.model.clone()),
+                name: self.name(),
+            },
+            ..Default::default()
+        })
+    }
+}
+
+/// Posts `messages` to an OpenAI-compatible chat completion endpoint and streams the reply.
+///
+/// The request asks for a streamed (SSE) response; each content delta is passed to
+/// `on_output` as it arrives. Endpoints that ignore `stream` and answer with a plain
+/// JSON completion are supported as well. When `timeout` is given, it bounds the whole
+/// request, including reading the streamed reply.
+pub fn openai_call<F>(
+    config: &OpenAiConfig,
+    messages: &[ChatMessage],
+    timeout: Option<Duration>,
+    mut on_output: F,
+) -> anyhow::Result<String>
+where
+    F: FnMut(&str) + Send + 'static,
+{
+    let mut body = json!({
+        "model": config.model,
+        "messages": messages,
+        "stream": true,
+    });
+    if let Some(temperature) = config.temperature {
+        body["temperature"] = json!(temperature);
+    }
+    if let Some(max_tokens) = config.max_tokens {
+        body["max_tokens"] = json!(max_tokens);
+    }
+
+    let endpoint = config.endpoint();
+    debug!("Posting {} messages to {}", messages.len(), endpoint);
+
+    let mut agent = ureq::AgentBuilder::new();
+    if let Some(timeout) = timeout {
+        agent = agent.timeout(timeout);
+    }
+    let mut request = agent
+        .build()
+        .post(&endpoint)
+        .set("Content-Type", "application/json")
+        .set("Accept", "text/event-stream");
+    if let Ok(api_key) = std::env::var(&config.api_key_env) {
+        request = request.set("Authorization", &format!("Bearer {}", api_key));
+    }
+
+    let response = match request.send_string(&serde_json::to_string(&body)?) {
+        Ok(response) => response,
+        Err(ureq::Error::Status(code, response)) => {
+            let detail = response.into_string().unwrap_or_default();
+            error!("Endpoint '{}' failed with {}: {}", endpoint, code, detail);
+            let message = format!("Endpoint '{}' failed with {}: {}", endpoint, code, detail);
+            // Rate limits and server errors may not happen again
+            if code == 429 || code >= 500 {
+                return Err(TransientError(message).into());
+            }
+            anyhow::bail!(message);
+        }
+        Err(e) => {
+            return Err(TransientError(e.to_string()))
+                .with_context(|| format!("Failed to reach endpoint '{}'", endpoint));
+        }
+    };
+
+    if response.content_type() != "text/event-stream" {
+        let completion: serde_json::Value = serde_json::from_reader(response.into_reader())
+            .with_context(|| format!("Invalid completion from '{}'", endpoint))?;
+        let text = completion["choices"][0]["message"]["content"]
+            .as_str()
+            .with_context(|| format!("No message content in completion from '{}'", endpoint))?
+            .to_string();
+        on_output(&text);
+        return Ok(text);
+    }
+
+    let reader = BufReader::new(response.into_reader());
+    let mut full_text = String::new();
+    for line in reader.lines() {
+        let line = line
+            .map_err(|e| TransientError(e.to_string()))
+            .with_context(|| format!("Failed to read stream from '{}'", endpoint))?;
+        let data = match line.strip_prefix(SSE_DATA_PREFIX) {
+            Some(data) => data.trim(),
+            None => continue,
+        };
+        if data == SSE_DONE_MARKER {
+            break;
+        }
+        let chunk: serde_json::Value = serde_json::from_str(data)
+            .with_context(|| format!("Invalid stream chunk from '{}': {}", endpoint, data))?;
+        if let Some(delta) = chunk["choices"][0]["delta"]["content"].as_str() {
+            debug!("Stream delta: {}", delta);
+            on_output(delta);
+            full_text.push_str(delta);
+        }
+    }
+
+    debug!("Stream finished. Output length: {}", full_text.len());
+    Ok(full_text)
+}
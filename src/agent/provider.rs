@@ -0,0 +1,304 @@
+//! Abstraction over the backends that answer `@answer` queries.
+//!
+//! A [`Provider`] receives the structured [`ModelContent`] together with the tag
+//! parameters, streams chunks of its reply through a callback and returns the final
+//! text along with some [`ProviderMetadata`]. The [`ProviderRegistry`] turns the value
+//! of the `provider` parameter into a concrete provider: a plain string selects the
+//! shell backend (or the backend named by a `<kind>:` prefix), an object selects the
+//! backend named by its `kind` field.
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::mock::MockProviderFactory;
+use super::openai::OpenAiProvider;
+use super::shell::ShellProvider;
+use crate::ast2::{JsonPlusEntity, Parameters};
+use crate::execute2::{ChatMessage, ModelContent, PromptConfig};
+
+/// Callback receiving each chunk of a provider reply as soon as it is available.
+pub type OutputCallback = Box<dyn FnMut(&str) + Send>;
+
+/// Builds a provider from the value of the `provider` parameter.
+pub type ProviderFactory =
+    Arc<dyn Fn(&JsonPlusEntity) -> anyhow::Result<Box<dyn Provider>> + Send + Sync>;
+
+/// Kind used for providers given as a plain string.
+pub const DEFAULT_PROVIDER_KIND: &str = "shell";
+
+/// Environment variable holding the UUID of the `@answer` anchor issuing a query.
+pub const ANCHOR_UUID_ENV: &str = "VESPE_ANCHOR_UUID";
+
+/// Environment variable holding the path of the context issuing a query.
+pub const CONTEXT_ENV: &str = "VESPE_CONTEXT";
+
+/// Everything a provider needs to answer a query.
+#[derive(Debug, Clone)]
+pub struct ProviderRequest {
+    /// The structured content collected for the query.
+    pub content: ModelContent,
+    /// How `content` should be flattened or split into messages.
+    pub prompt_config: PromptConfig,
+    /// The parameters of the `@answer` tag issuing the query.
+    pub parameters: Parameters,
+    /// Maximum duration of a single call; providers abort calls running longer.
+    pub timeout: Option<Duration>,
+    /// Variables describing where the query comes from (e.g. [`ANCHOR_UUID_ENV`]), exported
+    /// to the commands run by providers.
+    pub environment: BTreeMap<String, String>,
+}
+
+impl ProviderRequest {
+    /// The query flattened into a single prompt string.
+    pub fn prompt(&self) -> String {
+        self.content.to_prompt(&self.prompt_config)
+    }
+
+    /// The query split into role-tagged chat messages.
+    pub fn messages(&self) -> Vec<ChatMessage> {
+        self.content.to_messages(&self.prompt_config)
+    }
+}
+
+/// Information about how a reply was produced.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProviderMetadata {
+    /// Kind of the provider that produced the reply (e.g. `shell`, `openai`).
+    pub provider: String,
+    /// Model that produced the reply, when known.
+    pub model: Option<String>,
+    /// Name of the provider that produced the reply, see [`Provider::name`].
+    #[serde(default)]
+    pub name: String,
+}
+
+/// A failed attempt at calling a provider.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProviderAttempt {
+    /// Number of the attempt, starting from 1.
+    pub attempt: u32,
+    /// Name of the provider called, see [`Provider::name`].
+    #[serde(default)]
+    pub provider: String,
+    /// Why the attempt failed, including the provider's error output when available.
+    pub error: String,
+}
+
+/// The final outcome of a provider call.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProviderResponse {
+    /// The complete reply text.
+    pub text: String,
+    /// Information about how the reply was produced.
+    pub metadata: ProviderMetadata,
+    /// The failed attempts that preceded the reply.
+    pub failed_attempts: Vec<ProviderAttempt>,
+}
+
+/// A failure of a provider call that may not happen again, e.g. a timeout, a command
+/// exiting with an error or an overloaded endpoint.
+///
+/// Providers return it, anywhere in the chain of their error, for the failures worth a
+/// retry; [`call_chain`] does not retry any other error, e.g. a command not found or a
+/// request refused by the endpoint.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct TransientError(pub String);
+
+/// Returns whether `error` is worth a retry, see [`TransientError`].
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|x| x.is::<TransientError>())
+}
+
+/// How many times a provider call is retried, and how long to wait in between.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Number of retries after the first failed attempt.
+    pub retries: u32,
+    /// Wait before the first retry; it doubles at every further retry.
+    pub retry_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 0,
+            retry_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Calls the providers of a fallback chain in order until one of them replies.
+///
+/// Each provider is retried according to `policy` before falling back to the next one;
+/// only transient failures are retried (see [`TransientError`]), the others fall back
+/// at once. A fresh output callback is obtained from `on_output` for every attempt. On success the
+/// response lists the failed attempts that preceded it; on failure all attempts are returned.
+pub fn call_chain<F>(
+    providers: &[Box<dyn Provider>],
+    request: &ProviderRequest,
+    policy: &RetryPolicy,
+    mut on_output: F,
+) -> std::result::Result<ProviderResponse, Vec<ProviderAttempt>>
+where
+    F: FnMut() -> OutputCallback,
+{
+    let mut failed_attempts = Vec::new();
+    for provider in providers {
+        let mut backoff = policy.retry_backoff;
+        for retry in 0..=policy.retries {
+            let attempt = failed_attempts.len() as u32 + 1;
+            match provider.call(request, on_output()) {
+                Ok(mut response) => {
+                    response.failed_attempts = failed_attempts;
+                    return Ok(response);
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Provider '{}' attempt {} failed: {:#}",
+                        provider.name(),
+                        attempt,
+                        e
+                    );
+                    failed_attempts.push(ProviderAttempt {
+                        attempt,
+                        provider: provider.name(),
+                        error: format!("{:#}", e),
+                    });
+                    if !is_transient(&e) {
+                        break;
+                    }
+                    if retry < policy.retries {
+                        std::thread::sleep(backoff);
+                        backoff *= 2;
+                    }
+                }
+            }
+        }
+    }
+    Err(failed_attempts)
+}
+
+/// A backend able to answer a query.
+pub trait Provider: Send + Sync {
+    /// A short human-readable identification of the provider (e.g. the command it runs).
+    fn name(&self) -> String;
+
+    /// Answers `request`, passing each chunk of the reply to `on_output` as it arrives.
+    fn call(
+        &self,
+        request: &ProviderRequest,
+        on_output: OutputCallback,
+    ) -> anyhow::Result<ProviderResponse>;
+}
+
+/// Maps provider kinds to the factories building them.
+#[derive(Clone)]
+pub struct ProviderRegistry {
+    factories: BTreeMap<String, ProviderFactory>,
+}
+
+impl std::fmt::Debug for ProviderRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProviderRegistry")
+            .field("kinds", &self.factories.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Default for ProviderRegistry {
+    /// Creates a registry with all the built-in providers; the script files of mock
+    /// providers are relative to the working directory.
+    fn default() -> Self {
+        Self::with_mock_factory(MockProviderFactory::default())
+    }
+}
+
+impl ProviderRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        ProviderRegistry {
+            factories: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a registry with all the built-in providers, for the project rooted at `root`:
+    /// the script files of mock providers are relative to it.
+    pub fn for_project(root: PathBuf) -> Self {
+        Self::with_mock_factory(MockProviderFactory::with_root(root))
+    }
+
+    /// Creates a registry with all the built-in providers, building mock providers with `mock`.
+    fn with_mock_factory(mock: MockProviderFactory) -> Self {
+        let mut registry = Self::new();
+        registry.register(DEFAULT_PROVIDER_KIND, |entity| {
+            Ok(Box::new(ShellProvider::from_json_plus(entity)?))
+        });
+        registry.register("openai", |entity| {
+            Ok(Box::new(OpenAiProvider::from_json_plus(entity)?))
+        });
+        registry.register("mock", move |entity| Ok(Box::new(mock.build(entity)?)));
+        registry
+    }
+
+    /// Registers `factory` under `kind`, replacing any previous registration.
+    pub fn register<F>(&mut self, kind: &str, factory: F)
+    where
+        F: Fn(&JsonPlusEntity) -> anyhow::Result<Box<dyn Provider>> + Send + Sync + 'static,
+    {
+        self.factories.insert(kind.to_string(), Arc::new(factory));
+    }
+
+    /// Returns the kind selected by the value of a `provider` parameter.
+    ///
+    /// A string starting with `<kind>:` for a registered kind selects that kind,
+    /// any other string selects the shell provider.
+    pub fn kind_of(&self, provider: &JsonPlusEntity) -> anyhow::Result<String> {
+        match provider {
+            JsonPlusEntity::NudeString(x)
+            | JsonPlusEntity::SingleQuotedString(x)
+            | JsonPlusEntity::DoubleQuotedString(x) => Ok(x
+                .split_once(':')
+                .map(|(kind, _)| kind)
+                .filter(|kind| self.factories.contains_key(*kind))
+                .unwrap_or(DEFAULT_PROVIDER_KIND)
+                .to_string()),
+            JsonPlusEntity::Object(x) => x
+                .get_as_string_only("kind")
+                .context("provider object requires a 'kind' string"),
+            x => anyhow::bail!("bad provider: {:?}", x),
+        }
+    }
+
+    /// Builds the provider described by the value of a `provider` parameter.
+    pub fn resolve(&self, provider: &JsonPlusEntity) -> anyhow::Result<Box<dyn Provider>> {
+        let kind = self.kind_of(provider)?;
+        let factory = self
+            .factories
+            .get(&kind)
+            .with_context(|| format!("unknown provider kind '{}'", kind))?;
+        factory(provider)
+    }
+
+    /// Builds the fallback chain described by the value of a `provider` parameter.
+    ///
+    /// An array lists the providers to try in order; any other value is a chain of one.
+    pub fn resolve_chain(
+        &self,
+        provider: &JsonPlusEntity,
+    ) -> anyhow::Result<Vec<Box<dyn Provider>>> {
+        match provider {
+            JsonPlusEntity::Array(providers) if providers.is_empty() => {
+                anyhow::bail!("provider chain cannot be empty")
+            }
+            JsonPlusEntity::Array(providers) => providers
+                .iter()
+                .map(|provider| self.resolve(provider))
+                .collect(),
+            provider => Ok(vec![self.resolve(provider)?]),
+        }
+    }
+}
@@ -1,41 +1,367 @@
 use anyhow::Context;
+use std::collections::BTreeMap;
 use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::thread;
+use std::time::{Duration, Instant};
 use tracing::{debug, error};
 
+use super::command::parse_command_template;
+use super::provider::{
+    OutputCallback, Provider, ProviderMetadata, ProviderRequest, ProviderResponse, TransientError,
+};
+use crate::ast2::JsonPlusEntity;
+
+/// How often a command running under a timeout is checked for completion.
+const TIMEOUT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Placeholder of command templates replaced by the prompt, as a single argument.
+pub const PROMPT_PLACEHOLDER: &str = "{{prompt}}";
+
+/// Placeholder of command templates replaced by the path of a temporary file holding the prompt.
+pub const PROMPT_FILE_PLACEHOLDER: &str = "{{prompt_file}}";
+
+/// Provider running a command that reads the prompt and answers on stdout.
+///
+/// Selected by a plain string `provider` parameter (e.g. `provider: "gemini -y"`), or by
+/// `provider: { kind: "shell", command: "gemini -y", shell: true }`. The prompt is written to
+/// the command's stdin, unless the command uses [`PROMPT_PLACEHOLDER`] or
+/// [`PROMPT_FILE_PLACEHOLDER`]. The variables of [`ProviderRequest::environment`] are
+/// exported to the command.
+///
+/// The command is split into arguments by [`parse_command_template`] and run directly; with
+/// `shell: true` (in the provider object or as a tag parameter) it is run by the platform
+/// shell instead, so that pipes and redirections work.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShellProvider {
+    /// The command line to run.
+    pub command: String,
+    /// Whether the command line is run by the platform shell.
+    pub shell: bool,
+}
+
+impl ShellProvider {
+    /// Reads the command from the value of the `provider` parameter.
+    pub fn from_json_plus(entity: &JsonPlusEntity) -> anyhow::Result<Self> {
+        match entity {
+            JsonPlusEntity::Object(x) => Ok(ShellProvider {
+                command: x
+                    .get_as_string_only("command")
+                    .context("shell provider requires a 'command' string")?,
+                shell: x.get_as_bool("shell").unwrap_or(false),
+            }),
+            x => Ok(ShellProvider {
+                command: x
+                    .as_string_only()
+                    .with_context(|| format!("bad shell provider: {:?}", x))?,
+                shell: false,
+            }),
+        }
+    }
+}
+
+impl Provider for ShellProvider {
+    fn name(&self) -> String {
+        format!("shell: {}", self.command)
+    }
+
+    fn call(
+        &self,
+        request: &ProviderRequest,
+        on_output: OutputCallback,
+    ) -> anyhow::Result<ProviderResponse> {
+        let use_shell = self.shell || request.parameters.get_as_bool("shell").unwrap_or(false);
+        let text = shell_call(
+            &self.command,
+            use_shell,
+            &request.prompt(),
+            &request.environment,
+            request.timeout,
+            on_output,
+        )?;
+        Ok(ProviderResponse {
+            text,
+            metadata: ProviderMetadata {
+                provider: "shell".to_string(),
+                model: None,
+                name: self.name(),
+            },
+            ..Default::default()
+        })
+    }
+}
+
+/// Extensions tried by [`find_program`] when `PATHEXT` is not set.
+#[cfg(windows)]
+const DEFAULT_PATHEXT: &str = ".COM;.EXE;.BAT;.CMD";
+
+/// Quotes `text` as a single argument for the platform shell.
+///
+/// On Windows, `cmd` expands variables even within quotes, so each `%` is left out of the
+/// quotes and escaped. A newline cannot be quoted for `cmd`, as it ends the command.
+pub fn shell_quote(text: &str) -> String {
+    #[cfg(windows)]
+    {
+        format!("\"{}\"", text.replace('"', "\"\"").replace('%', "\"^%\""))
+    }
+    #[cfg(not(windows))]
+    {
+        format!("'{}'", text.replace('\'', "'\\''"))
+    }
+}
+
+/// Returns the path of `program` in the directories of `path`, trying each extension of
+/// `pathext` (e.g. `.COM;.EXE;.BAT;.CMD`), as the Windows shell does.
+///
+/// Returns `None` if `program` has an extension or a directory, or is not found.
+#[cfg(any(windows, test))]
+pub(crate) fn find_program(
+    program: &str,
+    path: &std::ffi::OsStr,
+    pathext: &str,
+) -> Option<PathBuf> {
+    let program_path = Path::new(program);
+    if program_path.components().count() != 1 || program_path.extension().is_some() {
+        return None;
+    }
+    std::env::split_paths(path).find_map(|dir| {
+        pathext
+            .split(';')
+            .filter(|x| !x.is_empty())
+            .map(|ext| dir.join(format!("{}{}", program, ext.to_lowercase())))
+            .find(|x| x.is_file())
+    })
+}
+
+/// Resolves `program` through `PATH` and `PATHEXT`, so that `.cmd` and `.bat` shims, like
+/// the ones installed by npm, are found; they are run through `cmd`, with their arguments
+/// escaped by the standard library.
+#[cfg(windows)]
+fn resolve_program(program: &str) -> PathBuf {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let pathext = std::env::var("PATHEXT").unwrap_or_else(|_| DEFAULT_PATHEXT.to_string());
+    find_program(program, &path, &pathext).unwrap_or_else(|| PathBuf::from(program))
+}
+
+/// Replaces [`PROMPT_PLACEHOLDER`] by `prompt` and [`PROMPT_FILE_PLACEHOLDER`] by
+/// `prompt_file` in `template`.
+///
+/// The template is scanned once, so placeholders within the replacements, e.g. in a prompt
+/// documenting them, are kept as they are.
+fn replace_placeholders(template: &str, prompt: &str, prompt_file: &str) -> String {
+    let mut output = String::new();
+    let mut rest = template;
+    loop {
+        let next = [
+            (PROMPT_PLACEHOLDER, prompt),
+            (PROMPT_FILE_PLACEHOLDER, prompt_file),
+        ]
+        .into_iter()
+        .filter_map(|(placeholder, value)| rest.find(placeholder).map(|i| (i, placeholder, value)))
+        .min_by_key(|(i, _, _)| *i);
+        match next {
+            Some((i, placeholder, value)) => {
+                output.push_str(&rest[..i]);
+                output.push_str(value);
+                rest = &rest[i + placeholder.len()..];
+            }
+            None => {
+                output.push_str(rest);
+                return output;
+            }
+        }
+    }
+}
+
+/// Temporary file holding a prompt, removed when dropped.
+struct PromptFile {
+    path: PathBuf,
+}
+
+impl PromptFile {
+    fn create(prompt: &str) -> anyhow::Result<Self> {
+        let path = std::env::temp_dir().join(format!("vespe-prompt-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, prompt)
+            .with_context(|| format!("Failed to write prompt file '{}'", path.display()))?;
+        Ok(PromptFile { path })
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PromptFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Output of a command run by [`run_command`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandOutput {
+    /// The exit code of the command, `None` if it was terminated by a signal.
+    pub exit_code: Option<i32>,
+    /// What the command wrote on stdout.
+    pub stdout: String,
+    /// What the command wrote on stderr.
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    /// Returns whether the command exited successfully.
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Runs `command_template`, streaming stdout lines to `on_output`, and returns its output.
+///
+/// See [`run_command`]; a command exiting unsuccessfully makes the call fail with a
+/// [`TransientError`].
 pub fn shell_call<F>(
     command_template: &str,
+    use_shell: bool,
     input: &str,
-    mut on_output: F,
+    environment: &BTreeMap<String, String>,
+    timeout: Option<Duration>,
+    on_output: F,
 ) -> anyhow::Result<String>
 where
     F: FnMut(&str) + Send + 'static,
 {
-    let mut command_parts = command_template.split_whitespace();
-    let program = command_parts
-        .next()
-        .context("Command template cannot be empty")?;
-    let args: Vec<&str> = command_parts.collect();
-
-    let mut command = {
-        #[cfg(windows)]
-        {
-            let mut cmd = Command::new("cmd");
-            cmd.arg("/C").arg(program).args(args.clone());
+    let output = run_command(
+        command_template,
+        use_shell,
+        input,
+        environment,
+        None,
+        timeout,
+        on_output,
+    )?;
+    if !output.success() {
+        error!("Command '{}' failed: {:?}", command_template, output.stderr);
+        return Err(TransientError(format!(
+            "Command '{}' failed: {:?}",
+            command_template, output.stderr
+        ))
+        .into());
+    }
+    debug!(
+        "Command executed successfully. Output length: {}",
+        output.stdout.len()
+    );
+    Ok(output.stdout)
+}
+
+/// Runs `command_template`, streaming stdout lines to `on_output`.
+///
+/// The template is split into arguments by [`parse_command_template`], expanding variables
+/// from `environment` and then from the process environment, and the program is run
+/// directly. With `use_shell` the template is instead run by `sh -c` (`cmd /C` on Windows).
+///
+/// `input` is written to the command's stdin, unless the template contains
+/// [`PROMPT_PLACEHOLDER`] (replaced by `input`) or [`PROMPT_FILE_PLACEHOLDER`] (replaced by
+/// the path of a temporary file holding `input`). Placeholders are replaced after the
+/// template is split, or quoted for the shell with `use_shell`, so the prompt is always
+/// passed as it is; `cmd` cannot take a multi-line prompt as an argument, so that fails.
+/// On Windows, programs are also resolved through `PATHEXT`, so that `.cmd` shims are found. The variables of `environment` are exported to the command, which
+/// runs in `working_dir` if given.
+///
+/// When `timeout` is given, a command still running after it is killed and the call fails
+/// with a [`TransientError`], as do errors while the command runs; failing to spawn the
+/// command is not transient.
+/// A command exiting unsuccessfully does not make the call fail.
+pub fn run_command<F>(
+    command_template: &str,
+    use_shell: bool,
+    input: &str,
+    environment: &BTreeMap<String, String>,
+    working_dir: Option<&Path>,
+    timeout: Option<Duration>,
+    mut on_output: F,
+) -> anyhow::Result<CommandOutput>
+where
+    F: FnMut(&str) + Send + 'static,
+{
+    let prompt_file = match command_template.contains(PROMPT_FILE_PLACEHOLDER) {
+        true => Some(PromptFile::create(input)?),
+        false => None,
+    };
+    let uses_stdin = prompt_file.is_none() && !command_template.contains(PROMPT_PLACEHOLDER);
+    let prompt_file_path = prompt_file
+        .as_ref()
+        .map(|x| x.path().to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    if cfg!(windows)
+        && use_shell
+        && command_template.contains(PROMPT_PLACEHOLDER)
+        && input.contains(['\n', '\r'])
+    {
+        anyhow::bail!(
+            "A multi-line prompt cannot be passed as {} through cmd, use {} instead",
+            PROMPT_PLACEHOLDER,
+            PROMPT_FILE_PLACEHOLDER
+        );
+    }
+
+    let mut command = match use_shell {
+        true => {
+            let command_line = replace_placeholders(
+                command_template,
+                &shell_quote(input),
+                &shell_quote(&prompt_file_path),
+            );
+            debug!("Executing shell command: {:?}", command_template);
+            #[cfg(windows)]
+            let mut cmd = {
+                let mut cmd = Command::new("cmd");
+                cmd.arg("/C");
+                cmd
+            };
+            #[cfg(not(windows))]
+            let mut cmd = {
+                let mut cmd = Command::new("sh");
+                cmd.arg("-c");
+                cmd
+            };
+            cmd.arg(command_line);
             cmd
         }
-        #[cfg(not(windows))]
-        {
-            let mut cmd = Command::new("sh");
-            cmd.arg("-c").arg(command_template);
+        false => {
+            let args = parse_command_template(command_template, |name| {
+                environment
+                    .get(name)
+                    .cloned()
+                    .or_else(|| std::env::var(name).ok())
+            })?;
+            let args: Vec<String> = args
+                .into_iter()
+                .map(|x| replace_placeholders(&x, input, &prompt_file_path))
+                .collect();
+            let (program, args) = args
+                .split_first()
+                .context("Command template cannot be empty")?;
+            debug!("Executing command: {:?} with args: {:?}", program, args);
+            #[cfg(windows)]
+            let program = resolve_program(program);
+            let mut cmd = Command::new(program);
+            cmd.args(args);
             cmd
         }
     };
 
-    debug!("Executing command: {:?} with args: {:?}", program, args);
+    if let Some(working_dir) = working_dir {
+        command.current_dir(working_dir);
+    }
     command
-        .stdin(Stdio::piped())
+        .envs(environment)
+        .stdin(match uses_stdin {
+            true => Stdio::piped(),
+            false => Stdio::null(),
+        })
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
 
@@ -46,9 +372,6 @@ where
         )
     })?;
 
-    debug!("Writing query to stdin: '{}'.", input);
-    child.stdin.as_mut().unwrap().write_all(input.as_bytes())?;
-
     let stdout = child.stdout.take().context("Failed to take stdout")?;
     let stderr = child.stderr.take().context("Failed to take stderr")?;
 
@@ -103,28 +426,69 @@ where
         buffer
     });
 
-    let status = child.wait()?;
+    // Written while the output is read, as the command may not read all its input before
+    // writing its output, e.g. `cat` with a large prompt
+    let stdin_handle = match child.stdin.take() {
+        Some(mut stdin) => {
+            let input = input.to_string();
+            Some(thread::spawn(move || {
+                debug!("Writing query to stdin: '{}'.", input);
+                match stdin.write_all(input.as_bytes()) {
+                    // The command may exit without reading its input
+                    Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {
+                        debug!("Command closed its stdin before reading the query.");
+                        Ok(())
+                    }
+                    // Dropping stdin closes it, so the command sees the end of its input
+                    x => x,
+                }
+            }))
+        }
+        None => None,
+    };
+
+    let transient = |e: std::io::Error| TransientError(e.to_string());
+    let status = match timeout {
+        None => child.wait().map_err(transient)?,
+        Some(timeout) => {
+            let deadline = Instant::now() + timeout;
+            loop {
+                if let Some(status) = child.try_wait().map_err(transient)? {
+                    break status;
+                }
+                if Instant::now() >= deadline {
+                    error!(
+                        "Command '{}' timed out after {:?}, killing it",
+                        command_template, timeout
+                    );
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    // Output threads are left to finish on their own, as processes spawned by
+                    // the command may still hold the pipes open.
+                    return Err(TransientError(format!(
+                        "Command '{}' timed out after {:?}",
+                        command_template, timeout
+                    ))
+                    .into());
+                }
+                thread::sleep(TIMEOUT_POLL_INTERVAL);
+            }
+        }
+    };
 
+    if let Some(stdin_handle) = stdin_handle {
+        stdin_handle
+            .join()
+            .expect("Failed to join stdin thread")
+            .map_err(transient)?;
+    }
     full_stdout = stdout_handle.join().expect("Failed to join stdout thread");
     full_stderr = stderr_handle.join().expect("Failed to join stderr thread");
 
     debug!("Command finished with status: {:?}", status);
-    if !status.success() {
-        error!(
-            "Command '{}' failed: {:?}",
-            command_template,
-            String::from_utf8_lossy(&full_stderr)
-        );
-        anyhow::bail!(
-            "Command '{}' failed: {:?}",
-            command_template,
-            String::from_utf8_lossy(&full_stderr)
-        );
-    }
-
-    debug!(
-        "Command executed successfully. Output length: {}",
-        full_stdout.len()
-    );
-    Ok(String::from_utf8_lossy(&full_stdout).to_string())
+    Ok(CommandOutput {
+        exit_code: status.code(),
+        stdout: String::from_utf8_lossy(&full_stdout).to_string(),
+        stderr: String::from_utf8_lossy(&full_stderr).to_string(),
+    })
 }
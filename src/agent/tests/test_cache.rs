@@ -0,0 +1,51 @@
+use super::cache::{CacheEntry, ResponseCache};
+use super::provider::ProviderMetadata;
+
+fn entry(reply: &str) -> CacheEntry {
+    CacheEntry {
+        reply: reply.to_string(),
+        metadata: ProviderMetadata {
+            provider: "shell".to_string(),
+            model: None,
+            name: "shell: echo".to_string(),
+        },
+    }
+}
+
+#[test]
+fn test_cache_key_depends_on_provider_and_query() {
+    let key = ResponseCache::key("gemini -y", "Hello?");
+
+    assert_eq!(key, ResponseCache::key("gemini -y", "Hello?"));
+    assert_ne!(key, ResponseCache::key("ollama run mistral", "Hello?"));
+    assert_ne!(key, ResponseCache::key("gemini -y", "Hello!"));
+}
+
+#[test]
+fn test_cache_store_and_lookup() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = ResponseCache::open(&dir.path().join("cache"));
+    let key = ResponseCache::key("shell", "query");
+
+    assert_eq!(cache.lookup(&key), None);
+    cache.store(&key, &entry("reply\n")).unwrap();
+    assert_eq!(cache.lookup(&key), Some(entry("reply\n")));
+}
+
+#[test]
+fn test_cache_stats_and_clear() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = ResponseCache::open(&dir.path().join("cache"));
+
+    assert_eq!(cache.stats().unwrap().entries, 0);
+    cache.store("a", &entry("first")).unwrap();
+    cache.store("b", &entry("second")).unwrap();
+
+    let stats = cache.stats().unwrap();
+    assert_eq!(stats.entries, 2);
+    assert!(stats.bytes > 0);
+
+    assert_eq!(cache.clear().unwrap(), 2);
+    assert_eq!(cache.stats().unwrap().entries, 0);
+    assert_eq!(cache.lookup("a"), None);
+}
@@ -0,0 +1,60 @@
+use super::command::parse_command_template;
+use std::collections::BTreeMap;
+
+fn parse(template: &str) -> anyhow::Result<Vec<String>> {
+    let variables = BTreeMap::from([
+        ("MODEL".to_string(), "mistral:7b".to_string()),
+        ("SPACED".to_string(), "a b".to_string()),
+    ]);
+    parse_command_template(template, |name| variables.get(name).cloned())
+}
+
+#[test]
+fn test_parse_splits_on_whitespace() {
+    assert_eq!(
+        parse("  ollama   run\tmistral ").unwrap(),
+        vec!["ollama", "run", "mistral"]
+    );
+    assert!(parse("").unwrap().is_empty());
+}
+
+#[test]
+fn test_parse_quoted_arguments() {
+    assert_eq!(
+        parse(r#"gemini -p 'It''s "fine"' "say \"hi\" \n" a\ b '' """#).unwrap(),
+        vec![
+            "gemini",
+            "-p",
+            "Its \"fine\"",
+            "say \"hi\" \\n",
+            "a b",
+            "",
+            ""
+        ]
+    );
+}
+
+#[test]
+fn test_parse_expands_variables() {
+    assert_eq!(
+        parse(r#"ollama run $MODEL "${MODEL}-q" '$MODEL' $SPACED $UNSET cost\$ $"#).unwrap(),
+        vec![
+            "ollama",
+            "run",
+            "mistral:7b",
+            "mistral:7b-q",
+            "$MODEL",
+            "a b",
+            "cost$",
+            "$"
+        ]
+    );
+}
+
+#[test]
+fn test_parse_rejects_unterminated() {
+    assert!(parse("echo 'open").is_err());
+    assert!(parse("echo \"open").is_err());
+    assert!(parse("echo ${MODEL").is_err());
+    assert!(parse("echo \\").is_err());
+}
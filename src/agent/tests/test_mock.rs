@@ -0,0 +1,115 @@
+use super::provider::{ProviderRegistry, ProviderRequest};
+use crate::ast2::{JsonPlusEntity, JsonPlusObject, Parameters};
+use crate::execute2::{ModelContent, ModelContentItem, PromptConfig, PromptFormat};
+use std::collections::BTreeMap;
+
+fn request(query: &str) -> ProviderRequest {
+    ProviderRequest {
+        content: ModelContent::from_item(ModelContentItem::user(query)),
+        prompt_config: PromptConfig {
+            agent: None,
+            format: PromptFormat::Parts,
+            with_agent_names: false,
+            with_invitation: false,
+        },
+        parameters: Parameters::new(),
+        timeout: None,
+        environment: BTreeMap::new(),
+    }
+}
+
+fn string(x: &str) -> JsonPlusEntity {
+    JsonPlusEntity::DoubleQuotedString(x.to_string())
+}
+
+fn mock(replies: Vec<JsonPlusEntity>, echo: bool) -> JsonPlusEntity {
+    let mut object = JsonPlusObject::new();
+    object.insert("kind".to_string(), string("mock"));
+    object.insert("replies".to_string(), JsonPlusEntity::Array(replies));
+    if echo {
+        object.insert("echo".to_string(), JsonPlusEntity::Boolean(true));
+    }
+    JsonPlusEntity::Object(object)
+}
+
+fn rule(pattern: &str, reply: &str) -> JsonPlusEntity {
+    let mut object = JsonPlusObject::new();
+    object.insert("match".to_string(), string(pattern));
+    object.insert("reply".to_string(), string(reply));
+    JsonPlusEntity::Object(object)
+}
+
+fn reply(registry: &ProviderRegistry, provider: &JsonPlusEntity, query: &str) -> String {
+    registry
+        .resolve(provider)
+        .unwrap()
+        .call(&request(query), Box::new(|_| {}))
+        .unwrap()
+        .text
+}
+
+#[test]
+fn test_mock_replies_in_sequence() {
+    let registry = ProviderRegistry::default();
+    let provider = mock(vec![string("first"), string("second")], false);
+
+    assert_eq!(reply(&registry, &provider, "a"), "first");
+    assert_eq!(reply(&registry, &provider, "b"), "second");
+    assert!(registry
+        .resolve(&provider)
+        .unwrap()
+        .call(&request("c"), Box::new(|_| {}))
+        .is_err());
+}
+
+#[test]
+fn test_mock_rules_match_query() {
+    let registry = ProviderRegistry::default();
+    let provider = mock(
+        vec![rule("(?i)capital of france", "Paris"), string("fallback")],
+        false,
+    );
+
+    assert_eq!(
+        reply(&registry, &provider, "What is the capital of France?"),
+        "Paris"
+    );
+    assert_eq!(reply(&registry, &provider, "Anything else?"), "fallback");
+    assert_eq!(
+        reply(&registry, &provider, "Capital of France, again?"),
+        "Paris"
+    );
+}
+
+#[test]
+fn test_mock_echo() {
+    let registry = ProviderRegistry::default();
+    let provider = mock(vec![string("scripted")], true);
+
+    assert_eq!(reply(&registry, &provider, "one"), "scripted");
+    assert_eq!(reply(&registry, &provider, "two"), request("two").prompt());
+}
+
+#[test]
+fn test_mock_script_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("replies.json");
+    std::fs::write(
+        &script,
+        r#"["from file", {"match": "^never$", "reply": "no"}]"#,
+    )
+    .unwrap();
+
+    let registry = ProviderRegistry::default();
+    let provider = string(&format!("mock:{}", script.display()));
+
+    assert_eq!(reply(&registry, &provider, "hello"), "from file");
+}
+
+#[test]
+fn test_mock_bad_pattern_fails() {
+    let registry = ProviderRegistry::default();
+    let provider = mock(vec![rule("(unclosed", "x")], false);
+
+    assert!(registry.resolve(&provider).is_err());
+}
@@ -0,0 +1,116 @@
+use super::openai::{openai_call, OpenAiConfig};
+use crate::execute2::{ChatMessage, ChatRole};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Serves a single canned HTTP response and hands back the received request body.
+fn serve_once(content_type: &str, body: &str) -> (String, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        content_type,
+        body.len(),
+        body
+    );
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut request_body = vec![0; content_length];
+        reader.read_exact(&mut request_body).unwrap();
+        reader.get_mut().write_all(response.as_bytes()).unwrap();
+        tx.send(String::from_utf8(request_body).unwrap()).unwrap();
+    });
+    (base_url, rx)
+}
+
+fn config(base_url: String) -> OpenAiConfig {
+    OpenAiConfig {
+        base_url,
+        model: "test-model".to_string(),
+        api_key_env: "VESPE_TEST_OPENAI_KEY_UNSET".to_string(),
+        temperature: Some(0.5),
+        max_tokens: None,
+    }
+}
+
+fn messages() -> Vec<ChatMessage> {
+    vec![
+        ChatMessage {
+            role: ChatRole::System,
+            content: "Be brief.".to_string(),
+        },
+        ChatMessage {
+            role: ChatRole::User,
+            content: "Say hello.".to_string(),
+        },
+    ]
+}
+
+#[test]
+fn test_openai_call_streams_deltas() {
+    let sse = concat!(
+        "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n",
+        "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n",
+        ": keep-alive\n\n",
+        "data: {\"choices\":[{\"delta\":{\"content\":\"lo!\"}}]}\n\n",
+        "data: [DONE]\n\n",
+    );
+    let (base_url, request_rx) = serve_once("text/event-stream", sse);
+
+    let chunks = Arc::new(Mutex::new(Vec::new()));
+    let chunks_sink = chunks.clone();
+    let reply = openai_call(&config(base_url), &messages(), None, move |chunk| {
+        chunks_sink.lock().unwrap().push(chunk.to_string())
+    })
+    .unwrap();
+
+    assert_eq!(reply, "Hello!");
+    assert_eq!(*chunks.lock().unwrap(), vec!["Hel", "lo!"]);
+
+    let request: serde_json::Value = serde_json::from_str(&request_rx.recv().unwrap()).unwrap();
+    assert_eq!(request["model"], "test-model");
+    assert_eq!(request["stream"], true);
+    assert_eq!(request["temperature"], 0.5);
+    assert_eq!(request["messages"][0]["role"], "system");
+    assert_eq!(request["messages"][1]["role"], "user");
+    assert_eq!(request["messages"][1]["content"], "Say hello.");
+}
+
+#[test]
+fn test_openai_call_non_streamed_completion() {
+    let body = r#"{"choices":[{"message":{"role":"assistant","content":"Hi there."}}]}"#;
+    let (base_url, _request_rx) = serve_once("application/json", body);
+
+    let reply = openai_call(&config(base_url), &messages(), None, |_| {}).unwrap();
+
+    assert_eq!(reply, "Hi there.");
+}
+
+#[test]
+fn test_openai_call_unreachable_endpoint() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+    drop(listener);
+
+    let result = openai_call(&config(base_url), &messages(), None, |_| {});
+
+    assert!(result.is_err());
+}
@@ -0,0 +1,547 @@
+use super::provider::{
+    call_chain, OutputCallback, Provider, ProviderMetadata, ProviderRegistry, ProviderRequest,
+    ProviderResponse, RetryPolicy, TransientError,
+};
+use super::shell::find_program;
+#[cfg(windows)]
+use super::shell::run_command;
+use crate::ast2::{JsonPlusEntity, JsonPlusObject, Parameters};
+use crate::execute2::{ModelContent, ModelContentItem, PromptConfig, PromptFormat};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+struct FixedProvider(String);
+
+impl Provider for FixedProvider {
+    fn name(&self) -> String {
+        "fixed".to_string()
+    }
+
+    fn call(
+        &self,
+        _request: &ProviderRequest,
+        mut on_output: OutputCallback,
+    ) -> anyhow::Result<ProviderResponse> {
+        on_output(&self.0);
+        Ok(ProviderResponse {
+            text: self.0.clone(),
+            metadata: ProviderMetadata {
+                provider: "fixed".to_string(),
+                model: None,
+                name: self.name(),
+            },
+            ..Default::default()
+        })
+    }
+}
+
+/// Fails the first `failures` calls, then replies with its name.
+struct FlakyProvider {
+    name: String,
+    failures: u32,
+    /// Whether the failures are transient, see [`TransientError`].
+    transient: bool,
+    calls: Arc<AtomicU32>,
+}
+
+impl Provider for FlakyProvider {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn call(
+        &self,
+        _request: &ProviderRequest,
+        _on_output: OutputCallback,
+    ) -> anyhow::Result<ProviderResponse> {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+        if call <= self.failures && self.transient {
+            return Err(TransientError(format!("failure {}", call)).into());
+        }
+        if call <= self.failures {
+            anyhow::bail!("failure {}", call);
+        }
+        Ok(ProviderResponse {
+            text: self.name.clone(),
+            metadata: ProviderMetadata {
+                provider: "flaky".to_string(),
+                model: None,
+                name: self.name(),
+            },
+            ..Default::default()
+        })
+    }
+}
+
+/// Builds a boxed [`FlakyProvider`], returning also its call counter.
+fn flaky(name: &str, failures: u32) -> (Box<dyn Provider>, Arc<AtomicU32>) {
+    let calls = Arc::new(AtomicU32::new(0));
+    let provider = FlakyProvider {
+        name: name.to_string(),
+        failures,
+        transient: true,
+        calls: calls.clone(),
+    };
+    (Box::new(provider), calls)
+}
+
+fn retry_policy(retries: u32) -> RetryPolicy {
+    RetryPolicy {
+        retries,
+        retry_backoff: Duration::ZERO,
+    }
+}
+
+fn request() -> ProviderRequest {
+    ProviderRequest {
+        content: ModelContent::from_item(ModelContentItem::user("Say hello.")),
+        prompt_config: PromptConfig {
+            agent: None,
+            format: PromptFormat::Parts,
+            with_agent_names: false,
+            with_invitation: false,
+        },
+        parameters: Parameters::new(),
+        timeout: None,
+        environment: BTreeMap::new(),
+    }
+}
+
+fn object(entries: &[(&str, &str)]) -> JsonPlusEntity {
+    let mut object = JsonPlusObject::new();
+    for (key, value) in entries {
+        object.insert(
+            key.to_string(),
+            JsonPlusEntity::DoubleQuotedString(value.to_string()),
+        );
+    }
+    JsonPlusEntity::Object(object)
+}
+
+#[test]
+fn test_kind_of_string_is_shell() {
+    let provider = JsonPlusEntity::DoubleQuotedString("gemini -y".to_string());
+    assert_eq!(
+        ProviderRegistry::default().kind_of(&provider).unwrap(),
+        "shell"
+    );
+}
+
+#[test]
+fn test_kind_of_string_with_registered_prefix() {
+    let registry = ProviderRegistry::default();
+    let mock = JsonPlusEntity::DoubleQuotedString("mock:replies.json".to_string());
+    let shell = JsonPlusEntity::DoubleQuotedString("ollama run mistral:7b".to_string());
+    assert_eq!(registry.kind_of(&mock).unwrap(), "mock");
+    assert_eq!(registry.kind_of(&shell).unwrap(), "shell");
+}
+
+#[test]
+fn test_kind_of_object_uses_kind_field() {
+    let provider = object(&[("kind", "openai"), ("model", "m")]);
+    assert_eq!(
+        ProviderRegistry::default().kind_of(&provider).unwrap(),
+        "openai"
+    );
+}
+
+#[test]
+fn test_kind_of_object_without_kind_fails() {
+    let provider = object(&[("model", "m")]);
+    assert!(ProviderRegistry::default().kind_of(&provider).is_err());
+}
+
+#[test]
+fn test_resolve_unknown_kind_fails() {
+    let registry = ProviderRegistry::default();
+    let provider = object(&[("kind", "nonexistent")]);
+    assert!(registry.resolve(&provider).is_err());
+}
+
+#[test]
+fn test_resolve_openai_requires_model() {
+    let registry = ProviderRegistry::default();
+    let provider = object(&[("kind", "openai"), ("base_url", "http://localhost")]);
+    assert!(registry.resolve(&provider).is_err());
+}
+
+#[test]
+fn test_registered_provider_is_resolved() {
+    let mut registry = ProviderRegistry::new();
+    registry.register("fixed", |_| {
+        Ok(Box::new(FixedProvider("Hello!".to_string())))
+    });
+
+    let provider = registry.resolve(&object(&[("kind", "fixed")])).unwrap();
+    let response = provider.call(&request(), Box::new(|_| {})).unwrap();
+
+    assert_eq!(response.text, "Hello!");
+    assert_eq!(response.metadata.provider, "fixed");
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_provider_receives_prompt() {
+    let registry = ProviderRegistry::default();
+    let provider = registry
+        .resolve(&JsonPlusEntity::DoubleQuotedString("cat".to_string()))
+        .unwrap();
+
+    let response = provider.call(&request(), Box::new(|_| {})).unwrap();
+
+    assert_eq!(response.text, request().prompt());
+    assert_eq!(response.metadata.provider, "shell");
+}
+
+#[test]
+fn test_call_chain_retries_and_recovers() {
+    let (provider, _) = flaky("flaky", 2);
+
+    let response = call_chain(&[provider], &request(), &retry_policy(2), || {
+        Box::new(|_| {})
+    })
+    .unwrap();
+
+    assert_eq!(response.text, "flaky");
+    assert_eq!(response.metadata.name, "flaky");
+    assert_eq!(response.failed_attempts.len(), 2);
+    assert_eq!(response.failed_attempts[0].attempt, 1);
+    assert_eq!(response.failed_attempts[1].error, "failure 2");
+}
+
+#[test]
+fn test_call_chain_gives_up() {
+    let (provider, calls) = flaky("flaky", 5);
+
+    let failed_attempts = call_chain(&[provider], &request(), &retry_policy(1), || {
+        Box::new(|_| {})
+    })
+    .unwrap_err();
+
+    assert_eq!(failed_attempts.len(), 2);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_call_chain_does_not_retry_permanent_failures() {
+    let calls = Arc::new(AtomicU32::new(0));
+    let first: Box<dyn Provider> = Box::new(FlakyProvider {
+        name: "first".to_string(),
+        failures: 5,
+        transient: false,
+        calls: calls.clone(),
+    });
+    let (second, _) = flaky("second", 0);
+
+    let response = call_chain(&[first, second], &request(), &retry_policy(3), || {
+        Box::new(|_| {})
+    })
+    .unwrap();
+
+    assert_eq!(response.metadata.name, "second");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert_eq!(response.failed_attempts.len(), 1);
+}
+
+#[test]
+fn test_call_chain_falls_back() {
+    let (first, first_calls) = flaky("first", 5);
+    let (second, second_calls) = flaky("second", 0);
+
+    let response = call_chain(&[first, second], &request(), &retry_policy(1), || {
+        Box::new(|_| {})
+    })
+    .unwrap();
+
+    assert_eq!(response.metadata.name, "second");
+    assert_eq!(first_calls.load(Ordering::SeqCst), 2);
+    assert_eq!(second_calls.load(Ordering::SeqCst), 1);
+    assert_eq!(response.failed_attempts.len(), 2);
+    assert!(response
+        .failed_attempts
+        .iter()
+        .all(|attempt| attempt.provider == "first"));
+}
+
+#[test]
+fn test_resolve_chain() {
+    let registry = ProviderRegistry::default();
+    let chain = JsonPlusEntity::Array(vec![
+        JsonPlusEntity::DoubleQuotedString("ollama run mistral".to_string()),
+        JsonPlusEntity::DoubleQuotedString("gemini -y".to_string()),
+    ]);
+
+    let providers = registry.resolve_chain(&chain).unwrap();
+
+    assert_eq!(
+        providers.iter().map(|x| x.name()).collect::<Vec<_>>(),
+        vec!["shell: ollama run mistral", "shell: gemini -y"]
+    );
+    assert!(registry
+        .resolve_chain(&JsonPlusEntity::Array(Vec::new()))
+        .is_err());
+}
+
+fn shell_provider(command: &str, shell: bool) -> Box<dyn Provider> {
+    let mut object = JsonPlusObject::new();
+    object.insert(
+        "kind".to_string(),
+        JsonPlusEntity::NudeString("shell".to_string()),
+    );
+    object.insert(
+        "command".to_string(),
+        JsonPlusEntity::DoubleQuotedString(command.to_string()),
+    );
+    object.insert("shell".to_string(), JsonPlusEntity::Boolean(shell));
+    ProviderRegistry::default()
+        .resolve(&JsonPlusEntity::Object(object))
+        .unwrap()
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_provider_timeout_kills_command() {
+    let registry = ProviderRegistry::default();
+    let provider = registry
+        .resolve(&JsonPlusEntity::DoubleQuotedString("sleep 10".to_string()))
+        .unwrap();
+    let mut request = request();
+    request.timeout = Some(Duration::from_millis(200));
+
+    let started = Instant::now();
+    let result = provider.call(&request, Box::new(|_| {}));
+
+    assert!(result.unwrap_err().to_string().contains("timed out"));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_shell_provider_missing_program_is_permanent() {
+    let provider = shell_provider("vespe-no-such-program", false);
+    let calls = Arc::new(AtomicU32::new(0));
+    let counter = calls.clone();
+
+    let failed_attempts = call_chain(&[provider], &request(), &retry_policy(3), move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Box::new(|_| {})
+    })
+    .unwrap_err();
+
+    assert_eq!(failed_attempts.len(), 1);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_provider_large_prompt() {
+    // More than a pipe buffer, that `cat` echoes before reading all of it
+    let mut request = request();
+    request.content = ModelContent::from_item(ModelContentItem::user(&"line\n".repeat(50_000)));
+    request.timeout = Some(Duration::from_secs(20));
+    let provider = shell_provider("cat", false);
+
+    let response = provider.call(&request, Box::new(|_| {})).unwrap();
+
+    assert_eq!(response.text, request.prompt());
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_provider_failure_reports_stderr() {
+    let provider = shell_provider("echo quota exceeded >&2; exit 3", true);
+
+    let result = provider.call(&request(), Box::new(|_| {}));
+
+    assert!(result.unwrap_err().to_string().contains("quota exceeded"));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_provider_prompt_placeholder() {
+    let registry = ProviderRegistry::default();
+    let provider = registry
+        .resolve(&JsonPlusEntity::DoubleQuotedString(
+            "printf '%s' {{prompt}}".to_string(),
+        ))
+        .unwrap();
+    let mut request = request();
+    request.content = ModelContent::from_item(ModelContentItem::user("It's \"quoted\" $HOME"));
+
+    let response = provider.call(&request, Box::new(|_| {})).unwrap();
+
+    assert_eq!(response.text, request.prompt());
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_provider_prompt_file_placeholder() {
+    let registry = ProviderRegistry::default();
+    let provider = registry
+        .resolve(&JsonPlusEntity::DoubleQuotedString(
+            "cat {{prompt_file}}".to_string(),
+        ))
+        .unwrap();
+
+    let response = provider.call(&request(), Box::new(|_| {})).unwrap();
+
+    assert_eq!(response.text, request().prompt());
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_provider_placeholders_in_prompt() {
+    let mut request = request();
+    request.content = ModelContent::from_item(ModelContentItem::user(
+        "Use {{prompt_file}} or {{prompt}} in the command.",
+    ));
+
+    for use_shell in [false, true] {
+        let provider = shell_provider("printf '%s' {{prompt}}", use_shell);
+        let response = provider.call(&request, Box::new(|_| {})).unwrap();
+        assert_eq!(response.text, request.prompt());
+    }
+
+    let provider = shell_provider("cat {{prompt_file}}", false);
+    let response = provider.call(&request, Box::new(|_| {})).unwrap();
+    assert_eq!(response.text, request.prompt());
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_provider_exports_environment() {
+    let registry = ProviderRegistry::default();
+    let provider = registry
+        .resolve(&JsonPlusEntity::DoubleQuotedString(
+            "echo \"$VESPE_ANCHOR_UUID\"".to_string(),
+        ))
+        .unwrap();
+    let mut request = request();
+    request
+        .environment
+        .insert("VESPE_ANCHOR_UUID".to_string(), "1234".to_string());
+
+    let response = provider.call(&request, Box::new(|_| {})).unwrap();
+
+    assert_eq!(response.text, "1234\n");
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_provider_quoted_arguments() {
+    let provider = shell_provider(r#"printf '%s|' 'one two' "three  four" five\ six"#, false);
+
+    let response = provider.call(&request(), Box::new(|_| {})).unwrap();
+
+    assert_eq!(response.text, "one two|three  four|five six|");
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_provider_expands_environment() {
+    let provider = shell_provider(
+        r#"printf '%s|' "${VESPE_CONTEXT}.md" '$VESPE_CONTEXT'"#,
+        false,
+    );
+    let mut request = request();
+    request
+        .environment
+        .insert("VESPE_CONTEXT".to_string(), "notes".to_string());
+
+    let response = provider.call(&request, Box::new(|_| {})).unwrap();
+
+    assert_eq!(response.text, "notes.md|$VESPE_CONTEXT|");
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_provider_pipes_require_shell() {
+    let piped = shell_provider("echo a b | tr ' ' '-'", true);
+    let not_piped = shell_provider("echo a b | tr ' ' '-'", false);
+
+    let response = piped.call(&request(), Box::new(|_| {})).unwrap();
+
+    assert_eq!(response.text, "a-b\n");
+    assert_eq!(
+        not_piped.call(&request(), Box::new(|_| {})).unwrap().text,
+        "a b | tr   -\n"
+    );
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_provider_shell_parameter() {
+    let provider = shell_provider("cat | tr 'a-z' 'A-Z'", false);
+    let mut request = request();
+    request.content = ModelContent::from_item(ModelContentItem::user("hello"));
+    request
+        .parameters
+        .insert("shell".to_string(), JsonPlusEntity::Boolean(true));
+
+    let response = provider.call(&request, Box::new(|_| {})).unwrap();
+
+    assert_eq!(response.text, request.prompt().to_uppercase());
+}
+
+#[test]
+fn test_find_program_pathext() {
+    let empty = tempfile::tempdir().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("tool.cmd"), "").unwrap();
+    std::fs::write(dir.path().join("other.txt"), "").unwrap();
+    let path = std::env::join_paths([empty.path(), dir.path()]).unwrap();
+
+    assert_eq!(
+        find_program("tool", &path, ".EXE;.CMD"),
+        Some(dir.path().join("tool.cmd"))
+    );
+    assert_eq!(find_program("tool.cmd", &path, ".EXE;.CMD"), None);
+    assert_eq!(find_program("other", &path, ".EXE;.CMD"), None);
+}
+
+#[cfg(windows)]
+#[test]
+fn test_shell_provider_windows_cmd_shim() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("vespe-shim-test.cmd"),
+        "@echo off\r\nmore\r\n",
+    )
+    .unwrap();
+    // Prepending a directory to PATH does not affect other tests
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let path = std::env::join_paths(
+        std::iter::once(dir.path().to_path_buf()).chain(std::env::split_paths(&path)),
+    )
+    .unwrap();
+    std::env::set_var("PATH", path);
+
+    let provider = shell_provider("vespe-shim-test", false);
+    let response = provider.call(&request(), Box::new(|_| {})).unwrap();
+
+    assert!(response.text.contains("Say hello."));
+}
+
+#[cfg(windows)]
+#[test]
+fn test_shell_provider_windows_cmd_quoting() {
+    // Variables are not expanded within the prompt
+    let output = run_command(
+        "echo {{prompt}}",
+        true,
+        "%PATH%",
+        &BTreeMap::new(),
+        None,
+        None,
+        |_| {},
+    )
+    .unwrap();
+    assert!(output.stdout.contains("PATH"));
+    assert!(!output.stdout.contains(&std::env::var("PATH").unwrap()));
+
+    // A newline would end the command
+    let provider = shell_provider("echo {{prompt}}", true);
+    let result = provider.call(&request(), Box::new(|_| {}));
+    assert!(result.unwrap_err().to_string().contains("{{prompt_file}}"));
+}
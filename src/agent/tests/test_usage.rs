@@ -0,0 +1,59 @@
+use super::usage::{CharTokenizer, Pricing, TokenUsage, UsageSummary};
+use crate::ast2::{JsonPlusEntity, JsonPlusObject};
+
+#[test]
+fn test_char_tokenizer_rounds_up() {
+    let tokenizer = CharTokenizer::default();
+
+    let usage = TokenUsage::estimate(&tokenizer, "12345678", "123456789", None);
+
+    assert_eq!(usage.input_tokens, 2);
+    assert_eq!(usage.output_tokens, 3);
+    assert_eq!(usage.cost, None);
+}
+
+#[test]
+fn test_usage_cost_from_pricing() {
+    let mut object = JsonPlusObject::new();
+    object.insert("input".to_string(), JsonPlusEntity::Integer(3));
+    object.insert("output".to_string(), JsonPlusEntity::Float(15.0));
+    let pricing = Pricing::from_json_plus(&JsonPlusEntity::Object(object)).unwrap();
+
+    let usage = TokenUsage::estimate(
+        &CharTokenizer {
+            chars_per_token: 1.0,
+        },
+        &"x".repeat(1000),
+        &"y".repeat(100),
+        Some(&pricing),
+    );
+
+    let cost = usage.cost.unwrap();
+    assert!((cost - 0.0045).abs() < 1e-12);
+}
+
+#[test]
+fn test_usage_summary_by_provider() {
+    let priced = TokenUsage {
+        input_tokens: 10,
+        output_tokens: 5,
+        cost: Some(0.5),
+    };
+    let unpriced = TokenUsage {
+        input_tokens: 1,
+        output_tokens: 2,
+        cost: None,
+    };
+    let mut summary = UsageSummary::default();
+
+    summary.record("a", &priced);
+    summary.record("a", &priced);
+    summary.record("b", &unpriced);
+
+    assert_eq!(summary.total.answers, 3);
+    assert_eq!(summary.total.usage.input_tokens, 21);
+    assert_eq!(summary.total.usage.output_tokens, 12);
+    assert_eq!(summary.total.usage.cost, Some(1.0));
+    assert_eq!(summary.by_provider["a"].answers, 2);
+    assert_eq!(summary.by_provider["b"].usage.cost, None);
+}
@@ -0,0 +1,185 @@
+//! Estimation and accounting of the tokens exchanged with providers.
+//!
+//! Token counts are estimated from the query and the reply of each answer by a
+//! [`Tokenizer`]; the default [`CharTokenizer`] uses a characters-per-token heuristic.
+//! When a [`Pricing`] is known for the provider, the cost of the answer is estimated too.
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use crate::ast2::JsonPlusEntity;
+
+/// Average number of characters per token assumed by default.
+pub const DEFAULT_CHARS_PER_TOKEN: f64 = 4.0;
+
+/// Counts the tokens of a text.
+pub trait Tokenizer: Send + Sync + Debug {
+    /// Returns the (estimated) number of tokens of `text`.
+    fn count_tokens(&self, text: &str) -> u64;
+}
+
+/// Tokenizer estimating one token every few characters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CharTokenizer {
+    /// Average number of characters per token.
+    pub chars_per_token: f64,
+}
+
+impl Default for CharTokenizer {
+    fn default() -> Self {
+        CharTokenizer {
+            chars_per_token: DEFAULT_CHARS_PER_TOKEN,
+        }
+    }
+}
+
+impl Tokenizer for CharTokenizer {
+    fn count_tokens(&self, text: &str) -> u64 {
+        (text.chars().count() as f64 / self.chars_per_token).ceil() as u64
+    }
+}
+
+/// Price of the tokens of a provider, per million tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pricing {
+    /// Price of one million input (prompt) tokens.
+    pub input: f64,
+    /// Price of one million output (reply) tokens.
+    pub output: f64,
+}
+
+impl Pricing {
+    /// Reads the pricing from the value of the `pricing` parameter.
+    pub fn from_json_plus(entity: &JsonPlusEntity) -> anyhow::Result<Self> {
+        serde_json::from_value(serde_json::Value::from(entity))
+            .with_context(|| format!("bad pricing: {}", entity.to_string()))
+    }
+
+    /// Returns the cost of `input_tokens` and `output_tokens`.
+    pub fn cost(&self, input_tokens: u64, output_tokens: u64) -> f64 {
+        (input_tokens as f64 * self.input + output_tokens as f64 * self.output) / 1_000_000.0
+    }
+}
+
+/// Tokens (and cost) of one or more answers.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    /// Estimated tokens of the queries.
+    pub input_tokens: u64,
+    /// Estimated tokens of the replies.
+    pub output_tokens: u64,
+    /// Estimated cost, if the pricing of the provider is known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+}
+
+impl TokenUsage {
+    /// Estimates the usage of an answer replying `reply` to `query`.
+    pub fn estimate(
+        tokenizer: &dyn Tokenizer,
+        query: &str,
+        reply: &str,
+        pricing: Option<&Pricing>,
+    ) -> Self {
+        let input_tokens = tokenizer.count_tokens(query);
+        let output_tokens = tokenizer.count_tokens(reply);
+        TokenUsage {
+            input_tokens,
+            output_tokens,
+            cost: pricing.map(|x| x.cost(input_tokens, output_tokens)),
+        }
+    }
+
+    /// Adds `other` to this usage.
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cost = match (self.cost, other.cost) {
+            (None, None) => None,
+            (x, y) => Some(x.unwrap_or(0.0) + y.unwrap_or(0.0)),
+        };
+    }
+}
+
+/// Usage of the answers of one provider.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProviderUsage {
+    /// Number of answers.
+    pub answers: usize,
+    /// Sum of the usage of the answers.
+    pub usage: TokenUsage,
+}
+
+impl ProviderUsage {
+    fn add(&mut self, usage: &TokenUsage) {
+        self.answers += 1;
+        self.usage.add(usage);
+    }
+}
+
+/// Usage of a set of answers, in total and by provider.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageSummary {
+    /// Usage of all the answers.
+    pub total: ProviderUsage,
+    /// Usage of the answers of each provider, by provider name.
+    pub by_provider: BTreeMap<String, ProviderUsage>,
+}
+
+impl UsageSummary {
+    /// Accounts an answer of `provider`.
+    pub fn record(&mut self, provider: &str, usage: &TokenUsage) {
+        self.total.add(usage);
+        self.by_provider
+            .entry(provider.to_string())
+            .or_default()
+            .add(usage);
+    }
+}
+
+/// Usage summary shared by the answers of one execution.
+///
+/// Besides the usage of completed answers, it counts the provider calls started and the
+/// answers left pending because a budget was exhausted.
+#[derive(Debug, Default)]
+pub struct UsageTally {
+    summary: Mutex<UsageSummary>,
+    calls: AtomicU64,
+    pending: Mutex<BTreeSet<Uuid>>,
+}
+
+impl UsageTally {
+    /// Counts a provider call being started.
+    pub fn start_call(&self) {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Returns the number of provider calls started so far.
+    pub fn calls(&self) -> u64 {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    /// Marks the answer `uuid` as pending, returning whether it was not already marked.
+    pub fn mark_pending(&self, uuid: &Uuid) -> bool {
+        self.pending.lock().unwrap().insert(*uuid)
+    }
+
+    /// Returns the number of answers left pending.
+    pub fn pending(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// Accounts an answer of `provider`.
+    pub fn record(&self, provider: &str, usage: &TokenUsage) {
+        self.summary.lock().unwrap().record(provider, usage);
+    }
+
+    /// Returns the usage accounted so far.
+    pub fn summary(&self) -> UsageSummary {
+        self.summary.lock().unwrap().clone()
+    }
+}
@@ -32,4 +32,4 @@ pub use model::tag::Tag;
 pub use model::text::Text;
 
 pub use error::{Ast2Error, Result};
-pub use parser::document::parse_document;
+pub use parser::document::{find_verbatim_ranges, parse_document};
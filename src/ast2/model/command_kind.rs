@@ -21,6 +21,31 @@ pub enum CommandKind {
     Task,
     /// Used in tandem with task
     Done,
+    /// Starts a conditional section, included only if its condition holds
+    If,
+    /// Starts the alternative branch of a conditional section
+    Else,
+    /// Ends a conditional section
+    EndIf,
+    /// Instantiates a template once for each item of a list
+    Foreach,
+    /// Inlines the output of a local command
+    Shell,
+    /// Includes the raw content of a project file
+    File,
+    /// Derives content from a source through a model
+    Derive,
+    /// Replaces the previous context with a summary
+    Compact,
+}
+
+impl CommandKind {
+    /// Returns whether the content of the anchors of this kind is kept verbatim, that is
+    /// neither parsed for tags, but `@repeat`, nor rendered as a template, e.g. the output
+    /// of a command.
+    pub fn is_verbatim(&self) -> bool {
+        matches!(self, CommandKind::Shell)
+    }
 }
 
 impl ToString for CommandKind {
@@ -35,6 +60,14 @@ impl ToString for CommandKind {
             CommandKind::Forget => "forget",
             CommandKind::Task => "task",
             CommandKind::Done => "done",
+            CommandKind::If => "if",
+            CommandKind::Else => "else",
+            CommandKind::EndIf => "endif",
+            CommandKind::Foreach => "foreach",
+            CommandKind::Shell => "shell",
+            CommandKind::File => "file",
+            CommandKind::Derive => "derive",
+            CommandKind::Compact => "compact",
         }
         .to_string()
     }
@@ -72,8 +72,12 @@ impl JsonPlusEntity {
             }
             JsonPlusEntity::Integer(x) => format!("{}{}", prefix, x),
             JsonPlusEntity::Float(x) => format!("{}{}", prefix, x),
-            JsonPlusEntity::SingleQuotedString(x) => format!("{}'{}'", prefix, x),
-            JsonPlusEntity::DoubleQuotedString(x) => format!("{}\"{}\"", prefix, x),
+            JsonPlusEntity::SingleQuotedString(x) => {
+                format!("{}'{}'", prefix, Self::_escape(x, '\''))
+            }
+            JsonPlusEntity::DoubleQuotedString(x) => {
+                format!("{}\"{}\"", prefix, Self::_escape(x, '"'))
+            }
             JsonPlusEntity::NudeString(x) => format!("{}{}", prefix, x),
             JsonPlusEntity::Object(x) => {
                 format!("{}{}", prefix, Self::_object_to_string_0(x, pre_indent))
@@ -83,6 +87,17 @@ impl JsonPlusEntity {
             }
         }
     }
+    /// Escapes backslashes and `quote` characters, as the parser of quoted strings expects.
+    fn _escape(x: &str, quote: char) -> String {
+        let mut escaped = String::with_capacity(x.len());
+        for c in x.chars() {
+            if c == '\\' || c == quote {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    }
     fn _array_to_string_0(array: &Vec<JsonPlusEntity>, pre_indent: &str) -> String {
         let mut s = format!("[");
         let n = array.len();
@@ -158,6 +173,22 @@ impl From<&JsonPlusEntity> for serde_json::Value {
     }
 }
 
+impl From<&serde_json::Value> for JsonPlusEntity {
+    fn from(value: &serde_json::Value) -> Self {
+        match value {
+            Value::Null => JsonPlusEntity::NudeString("null".to_string()),
+            Value::Bool(x) => JsonPlusEntity::Boolean(*x),
+            Value::Number(x) => match x.as_i64() {
+                Some(x) => JsonPlusEntity::Integer(x),
+                None => JsonPlusEntity::Float(x.as_f64().unwrap_or_default()),
+            },
+            Value::String(x) => JsonPlusEntity::DoubleQuotedString(x.clone()),
+            Value::Array(x) => JsonPlusEntity::Array(x.iter().map(|x| x.into()).collect()),
+            Value::Object(x) => JsonPlusEntity::Object(x.into()),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct JsonPlusObject {
     pub properties: BTreeMap<String, JsonPlusEntity>,
@@ -174,6 +205,14 @@ impl From<&JsonPlusObject> for serde_json::Value {
     }
 }
 
+impl From<&Map<String, Value>> for JsonPlusObject {
+    fn from(map: &Map<String, Value>) -> Self {
+        JsonPlusObject {
+            properties: map.iter().map(|(x, y)| (x.clone(), y.into())).collect(),
+        }
+    }
+}
+
 impl JsonPlusObject {
     pub fn new() -> Self {
         JsonPlusObject {
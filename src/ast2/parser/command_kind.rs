@@ -14,6 +14,14 @@ pub(crate) fn _try_parse_command_kind<'doc>(
         ("forget", CommandKind::Forget),
         ("task", CommandKind::Task),
         ("done", CommandKind::Done),
+        ("if", CommandKind::If),
+        ("else", CommandKind::Else),
+        ("endif", CommandKind::EndIf),
+        ("foreach", CommandKind::Foreach),
+        ("shell", CommandKind::Shell),
+        ("file", CommandKind::File),
+        ("derive", CommandKind::Derive),
+        ("compact", CommandKind::Compact),
     ];
 
     for (name, kind) in tags_list {
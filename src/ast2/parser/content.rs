@@ -1,4 +1,6 @@
-use super::super::{Ast2Error, Comment, Content, Range, Result, Text};
+use uuid::Uuid;
+
+use super::super::{AnchorKind, Ast2Error, CommandKind, Comment, Content, Range, Result, Text};
 use super::anchor::_try_parse_anchor;
 use super::comment::_try_parse_comment;
 use super::parser::Parser;
@@ -9,6 +11,9 @@ pub(crate) fn parse_content<'doc>(parser: Parser<'doc>) -> Result<(Vec<Content>,
     let mut contents = Vec::new();
     let mut p_current = parser; // Takes ownership
 
+    // The anchor whose content is verbatim, when inside one
+    let mut verbatim: Option<Uuid> = None;
+
     // The core parsing loop. It processes the document line by line.
     // Each line must start at column 1 and is attempted to be parsed as a Tag,
     // an Anchor, or plain Text, in that order of precedence.
@@ -21,7 +26,29 @@ pub(crate) fn parse_content<'doc>(parser: Parser<'doc>) -> Result<(Vec<Content>,
             });
         }
 
-        if let Some((comment, p_next)) = _try_parse_comment(&p_current)? {
+        // Inside a verbatim anchor only its end and `@repeat` are parsed, everything
+        // else is text
+        if let Some(uuid) = verbatim {
+            match _try_parse_anchor(&p_current) {
+                Ok(Some((anchor, p_next)))
+                    if anchor.uuid == uuid && anchor.kind == AnchorKind::End =>
+                {
+                    contents.push(Content::Anchor(anchor));
+                    p_current = p_next;
+                    verbatim = None;
+                    continue;
+                }
+                _ => {}
+            }
+            match _try_parse_tag(&p_current) {
+                Ok(Some((tag, p_next))) if tag.command == CommandKind::Repeat => {
+                    contents.push(Content::Tag(tag));
+                    p_current = p_next;
+                    continue;
+                }
+                _ => {}
+            }
+        } else if let Some((comment, p_next)) = _try_parse_comment(&p_current)? {
             let latest_content = contents.pop();
             match latest_content {
                 Some(Content::Comment(prev_comment)) => {
@@ -49,13 +76,16 @@ pub(crate) fn parse_content<'doc>(parser: Parser<'doc>) -> Result<(Vec<Content>,
             continue;
         }
 
-        if let Some((tag, p_next)) = _try_parse_tag(&p_current)? {
+        if verbatim.is_some() {
+            // Parsed as text below
+        } else if let Some((tag, p_next)) = _try_parse_tag(&p_current)? {
             contents.push(Content::Tag(tag));
             p_current = p_next;
             continue;
-        }
-
-        if let Some((anchor, p_next)) = _try_parse_anchor(&p_current)? {
+        } else if let Some((anchor, p_next)) = _try_parse_anchor(&p_current)? {
+            if anchor.kind == AnchorKind::Begin && anchor.command.is_verbatim() {
+                verbatim = Some(anchor.uuid);
+            }
             contents.push(Content::Anchor(anchor));
             p_current = p_next;
             continue;
@@ -1,4 +1,5 @@
-use super::super::{Document, Range, Result};
+use super::super::{AnchorKind, Document, Range, Result};
+use super::anchor::_try_parse_anchor;
 use super::content::parse_content;
 use super::parser::Parser;
 
@@ -24,3 +25,41 @@ pub fn parse_document(document: &str) -> Result<Document> {
         range: Range { begin, end },
     })
 }
+
+/// Returns the ranges of the content of the verbatim anchors of a document, see
+/// [`CommandKind::is_verbatim`](super::super::CommandKind::is_verbatim).
+///
+/// Only the anchors are parsed, so that the document can be a template not yet rendered;
+/// lines that are not anchors are skipped, even when they are malformed.
+pub fn find_verbatim_ranges(document: &str) -> Vec<Range> {
+    let mut ranges = Vec::new();
+    let mut begin = None;
+    let mut p_current = Parser::new(document);
+    while !p_current.is_eod() {
+        if let Ok(Some((anchor, p_next))) = _try_parse_anchor(&p_current) {
+            match (begin, anchor.kind) {
+                (None, AnchorKind::Begin) if anchor.command.is_verbatim() => {
+                    begin = Some((anchor.uuid, anchor.range.end));
+                }
+                (Some((uuid, content_begin)), AnchorKind::End) if anchor.uuid == uuid => {
+                    ranges.push(Range {
+                        begin: content_begin,
+                        end: anchor.range.begin,
+                    });
+                    begin = None;
+                }
+                _ => {}
+            }
+            p_current = p_next;
+            continue;
+        }
+        // Skip the line
+        while let Some((c, p_next)) = p_current.advance_immutable() {
+            p_current = p_next;
+            if c == '\n' {
+                break;
+            }
+        }
+    }
+    ranges
+}
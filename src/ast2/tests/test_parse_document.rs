@@ -1,6 +1,6 @@
 use super::super::{AnchorKind, Ast2Error, CommandKind, Content};
 use super::content::parse_content;
-use super::document::parse_document;
+use super::document::{find_verbatim_ranges, parse_document};
 use super::parser::Parser;
 use serde_json::json;
 use uuid::Uuid;
@@ -99,3 +99,35 @@ fn test_parse_document_with_error() {
         Err(Ast2Error::MissingParameterValue { .. })
     ));
 }
+
+#[test]
+fn test_parse_document_verbatim_anchor() {
+    let uuid_str = "123e4567-e89b-12d3-a456-426614174000";
+    let body = "@include other\n%% not a comment\n<!-- answer-x -->\n";
+    let doc = format!(
+        "<!-- shell-{0}:begin -->\n{1}<!-- shell-{0}:end -->\n@tag\n",
+        uuid_str, body
+    );
+    let document = parse_document(&doc).unwrap();
+
+    assert_eq!(document.content.len(), 4);
+    if let Content::Text(text) = &document.content[1] {
+        assert_eq!(text.content, body);
+    } else {
+        panic!("Expected Text");
+    }
+    assert!(matches!(&document.content[2], Content::Anchor(a) if a.kind == AnchorKind::End));
+    assert!(matches!(&document.content[3], Content::Tag(_)));
+
+    let ranges = find_verbatim_ranges(&format!("{{{{unrendered}}}}\n{}", doc));
+    assert_eq!(ranges.len(), 1);
+    assert_eq!(
+        ranges[0].end.offset - ranges[0].begin.offset,
+        body.chars().count()
+    );
+
+    // But `@repeat` is still a tag
+    let doc = doc.replace("%% not a comment\n", "@repeat\n");
+    let document = parse_document(&doc).unwrap();
+    assert!(matches!(&document.content[2], Content::Tag(t) if t.command == CommandKind::Repeat));
+}
@@ -22,3 +22,16 @@ fn test_try_parse_jsonplus_array() {
     //assert_eq!(value, Vec::new());
     assert!(!value.is_empty());
 }
+
+#[test]
+fn test_jsonplus_object_to_string_round_trip_quotes() {
+    let doc = r#"{ a: "say \"hi\"", b: 'it\'s', c: "C:\\new" }"#;
+    let parser = Parser::new(doc);
+    let (value, _p_next) = _try_parse_jsonplus_object(&parser).unwrap().unwrap();
+    assert_eq!(value.get_as_string_only("a").unwrap(), "say \"hi\"");
+    assert_eq!(value.get_as_string_only("c").unwrap(), "C:\\new");
+    let text = value.to_string();
+    let parser = Parser::new(&text);
+    let (round_trip, _p_next) = _try_parse_jsonplus_object(&parser).unwrap().unwrap();
+    assert_eq!(round_trip, value);
+}
@@ -24,6 +24,27 @@ fn test_try_parse_command_kind_valid() {
     assert_eq!(p_next.remain(), " rest");
 }
 
+#[test]
+fn test_try_parse_command_kind_conditionals() {
+    let doc = "if { var: mode } rest";
+    let parser = Parser::new(doc);
+    let (kind, p_next) = _try_parse_command_kind(&parser).unwrap().unwrap();
+    assert_eq!(kind, CommandKind::If);
+    assert_eq!(p_next.remain(), " { var: mode } rest");
+
+    let doc = "else rest";
+    let parser = Parser::new(doc);
+    let (kind, p_next) = _try_parse_command_kind(&parser).unwrap().unwrap();
+    assert_eq!(kind, CommandKind::Else);
+    assert_eq!(p_next.remain(), " rest");
+
+    let doc = "endif rest";
+    let parser = Parser::new(doc);
+    let (kind, p_next) = _try_parse_command_kind(&parser).unwrap().unwrap();
+    assert_eq!(kind, CommandKind::EndIf);
+    assert_eq!(p_next.remain(), " rest");
+}
+
 #[test]
 fn test_try_parse_command_kind_invalid() {
     let doc = "invalid_command rest";
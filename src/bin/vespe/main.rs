@@ -1,13 +1,17 @@
 use anyhow::Result;
-use clap::{ArgAction, Parser, Subcommand};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 use handlebars::Handlebars;
 use serde_json::json;
+use std::collections::BTreeMap;
 use std::io::{self, Read};
 use std::path::PathBuf;
 use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
 
+use vespe::agent::cassette::CassetteMode;
+use vespe::agent::usage::ProviderUsage;
+use vespe::config::{ProviderProfile, PROVIDER_PROFILE_PREFIX};
 use vespe::error::Error;
 use vespe::execute2::{AnchorAnalysis, AnchorState, ContextAnalysis};
 use vespe::project::Project;
@@ -51,6 +55,21 @@ enum Commands {
     },
     /// Watches for changes in context files and re-executes them.
     Watch {},
+    /// Shows the estimated token usage (and cost) of all the answers in the project.
+    Stats {},
+    /// Manages the cache of provider replies.
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// Removes every cached reply.
+    Clear {},
+    /// Shows the number and size of the cached replies.
+    Stats {},
 }
 
 #[derive(Subcommand)]
@@ -69,6 +88,26 @@ enum ProjectCommands {
     },
     /// Lists the auxiliary paths in the project configuration.
     ListAuxPaths {},
+    /// Adds (or replaces) a named provider profile, usable as `provider: "@NAME"`.
+    AddProvider {
+        /// The name of the profile.
+        #[arg(value_name = "NAME")]
+        name: String,
+        /// A shell command, or a JSON object or array describing the provider.
+        #[arg(value_name = "PROVIDER")]
+        provider: String,
+        /// Default parameter of the answers using the profile (value parsed as JSON if possible).
+        #[arg(short = 'P', long = "param", value_name = "KEY=VALUE", action = ArgAction::Append)]
+        params: Option<Vec<String>>,
+    },
+    /// Removes a named provider profile.
+    RemoveProvider {
+        /// The name of the profile to remove.
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+    /// Lists the named provider profiles.
+    ListProviders {},
 }
 
 #[derive(Subcommand)]
@@ -102,6 +141,12 @@ enum ContextCommands {
         /// Alternative output path for the context.
         #[arg(short = 'O', long = "output-path", value_name = "PATH")]
         output_path: Option<PathBuf>,
+        /// Record provider replies into the project cassette, or replay them from it.
+        #[arg(long, value_enum, value_name = "MODE")]
+        cassette: Option<CassetteArg>,
+        /// Answer identical queries from the project cache instead of calling providers again.
+        #[arg(long)]
+        cache: bool,
     },
     /// Analyzes a context file.
     Analyze {
@@ -113,6 +158,23 @@ enum ContextCommands {
     },
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum CassetteArg {
+    /// Store every provider reply in the cassette.
+    Record,
+    /// Serve replies from the cassette, failing on queries never recorded.
+    Replay,
+}
+
+impl From<CassetteArg> for CassetteMode {
+    fn from(arg: CassetteArg) -> Self {
+        match arg {
+            CassetteArg::Record => CassetteMode::Record,
+            CassetteArg::Replay => CassetteMode::Replay,
+        }
+    }
+}
+
 fn get_context_name(today: bool, name: Option<String>, format_str: &str) -> Result<String> {
     let context_name = if today {
         Ok(chrono::Local::now().format(format_str).to_string())
@@ -176,6 +238,8 @@ fn main() -> Result<()> {
                     defines,
                     aux_paths,
                     output_path,
+                    cassette,
+                    cache,
                 } => {
                     let context_name = get_context_name(today, name, DIARY_CONTEXT_FORMAT)?;
                     tracing::info!(
@@ -191,6 +255,8 @@ fn main() -> Result<()> {
                         defines,
                         additional_aux_paths: aux_paths,
                         output_path,
+                        cassette: cassette.map(CassetteMode::from),
+                        cache,
                     };
                     let content = project.execute_context(input_data)?;
                     tracing::info!("Context '{}' executed successfully.", context_name);
@@ -239,17 +305,108 @@ fn main() -> Result<()> {
                         }
                     }
                 }
+                ProjectCommands::AddProvider {
+                    name,
+                    provider,
+                    params,
+                } => {
+                    let name = name
+                        .strip_prefix(PROVIDER_PROFILE_PREFIX)
+                        .unwrap_or(&name)
+                        .to_string();
+                    let provider = match serde_json::from_str::<serde_json::Value>(&provider) {
+                        Ok(x) if x.is_object() || x.is_array() => x,
+                        _ => serde_json::Value::String(provider),
+                    };
+                    let mut parameters = BTreeMap::new();
+                    for param in params.unwrap_or_default() {
+                        let (key, value) = param.split_once('=').ok_or_else(|| {
+                            anyhow::anyhow!("Invalid parameter '{}', expected KEY=VALUE", param)
+                        })?;
+                        let value = serde_json::from_str(value)
+                            .unwrap_or_else(|_| serde_json::Value::String(value.to_string()));
+                        parameters.insert(key.to_string(), value);
+                    }
+                    project.add_provider_profile(
+                        name.clone(),
+                        ProviderProfile {
+                            provider,
+                            parameters,
+                        },
+                    )?;
+                    tracing::info!("Added provider profile: {}", name);
+                }
+                ProjectCommands::RemoveProvider { name } => {
+                    let name = name.strip_prefix(PROVIDER_PROFILE_PREFIX).unwrap_or(&name);
+                    project.remove_provider_profile(name)?;
+                    tracing::info!("Removed provider profile: {}", name);
+                }
+                ProjectCommands::ListProviders {} => {
+                    let profiles = project.get_provider_profiles();
+                    if profiles.is_empty() {
+                        println!("No provider profiles configured.");
+                    } else {
+                        println!("Provider profiles:");
+                        for (name, profile) in profiles {
+                            println!(
+                                "  - {}{}: {}",
+                                PROVIDER_PROFILE_PREFIX, name, profile.provider
+                            );
+                            for (key, value) in &profile.parameters {
+                                println!("      {} = {}", key, value);
+                            }
+                        }
+                    }
+                }
             }
         }
         Commands::Watch {} => {
             let project = Project::find(&project_path)?;
             watch::watch(&project)?;
         }
+        Commands::Stats {} => {
+            let project = Project::find(&project_path)?;
+            let summary = project.usage_summary()?;
+            if summary.total.answers == 0 {
+                println!("No answers found.");
+            } else {
+                for (provider, usage) in &summary.by_provider {
+                    display_usage(provider, usage);
+                }
+                display_usage("Total", &summary.total);
+            }
+        }
+        Commands::Cache { command } => {
+            let project = Project::find(&project_path)?;
+            let cache = project.response_cache();
+            match command {
+                CacheCommands::Clear {} => {
+                    let removed = cache.clear()?;
+                    println!("Removed {} cached replies.", removed);
+                }
+                CacheCommands::Stats {} => {
+                    let stats = cache.stats()?;
+                    println!("Cache: {}", project.cache_path().display());
+                    println!("  Entries: {}", stats.entries);
+                    println!("  Size: {} bytes", stats.bytes);
+                }
+            }
+        }
     }
 
     Ok(())
 }
 
+fn display_usage(label: &str, usage: &ProviderUsage) {
+    println!("{}:", label);
+    println!("  Answers: {}", usage.answers);
+    println!("  Input tokens: ~{}", usage.usage.input_tokens);
+    println!("  Output tokens: ~{}", usage.usage.output_tokens);
+    if let Some(cost) = usage.usage.cost {
+        println!("  Estimated cost: {:.4}", cost);
+    }
+}
+
 fn read_input() -> Result<Option<String>> {
     let (tx, rx) = mpsc::channel::<Result<String, Error>>();
 
@@ -279,15 +436,22 @@ fn display_analysis_report(analysis: &ContextAnalysis) -> Result<()> {
         let answer_anchor_count = analysis
             .anchors
             .values()
-            .filter(|aa| matches!(aa.state, AnchorState::Answer(_)))
+            .filter(|aa| {
+                matches!(
+                    aa.state,
+                    AnchorState::Answer(_) | AnchorState::Derive(_) | AnchorState::Compact(_)
+                )
+            })
             .count();
 
         for (_, anchor_analysis) in &analysis.anchors {
             match &anchor_analysis.state {
-                AnchorState::Answer(_) => {
+                AnchorState::Answer(_) | AnchorState::Derive(_) | AnchorState::Compact(_) => {
                     display_answer_analysis(anchor_analysis, answer_anchor_count)
                 }
                 AnchorState::Inline(_) => display_inline_analysis(anchor_analysis),
+                AnchorState::Foreach(_) => display_foreach_analysis(anchor_analysis),
+                AnchorState::Shell(_) => display_shell_analysis(anchor_analysis),
                 AnchorState::Task(_) => display_task_analysis(anchor_analysis),
             }
             println!("------------------------------------------------------------");
@@ -297,7 +461,9 @@ fn display_analysis_report(analysis: &ContextAnalysis) -> Result<()> {
 }
 
 fn display_answer_analysis(analysis: &AnchorAnalysis, answer_anchor_count: usize) {
-    if let AnchorState::Answer(state) = &analysis.state {
+    if let AnchorState::Answer(state) | AnchorState::Derive(state) | AnchorState::Compact(state) =
+        &analysis.state
+    {
         let truncation_limit = if answer_anchor_count == 1 {
             usize::MAX // No truncation
         } else if answer_anchor_count >= 2 && answer_anchor_count <= 5 {
@@ -306,7 +472,12 @@ fn display_answer_analysis(analysis: &AnchorAnalysis, answer_anchor_count: usize
             DEFAULT_TRUNCATION_LIMIT
         };
 
-        println!("Anchor (Answer): {}", analysis.anchor.uuid);
+        let kind = match analysis.state {
+            AnchorState::Derive(_) => "Derive",
+            AnchorState::Compact(_) => "Compact",
+            _ => "Answer",
+        };
+        println!("Anchor ({}): {}", kind, analysis.anchor.uuid);
         println!("  Status: {:?}", state.status);
 
         let query_display = if state.query.len() > truncation_limit {
@@ -327,6 +498,41 @@ fn display_answer_analysis(analysis: &AnchorAnalysis, answer_anchor_count: usize
             query_display
         );
 
+        if !state.pending_reason.is_empty() {
+            println!("  Pending: {}", state.pending_reason);
+        }
+        if !state.metadata.name.is_empty() {
+            println!("  Provider: {}", state.metadata.name);
+        }
+        if !state.raw_reply.is_empty() {
+            println!(
+                "  Tokens: ~{} in, ~{} out{}",
+                state.usage.input_tokens,
+                state.usage.output_tokens,
+                state
+                    .usage
+                    .cost
+                    .map(|x| format!(", estimated cost {:.4}", x))
+                    .unwrap_or_default()
+            );
+        }
+
+        for attempt in &state.failed_attempts {
+            println!(
+                "+ Failed attempt {} ({}): +++++++++++++++++++++++++++++++++++\n{}",
+                attempt.attempt, attempt.provider, attempt.error
+            );
+        }
+        if !state.choices.is_empty() {
+            println!("  Choices: {}", state.choices.join(", "));
+        }
+        if !state.reasoning.is_empty() {
+            println!("  Reasoning: {}", state.reasoning);
+        }
+        for (index, error) in state.format_errors.iter().enumerate() {
+            println!("  Rejected reply {}: {}", index + 1, error);
+        }
+
         let reply_display = if state.reply.len() > truncation_limit {
             format!(
                 "{:.limit$}...",
@@ -377,6 +583,34 @@ fn display_inline_analysis(analysis: &AnchorAnalysis) {
     }
 }
 
+fn display_foreach_analysis(analysis: &AnchorAnalysis) {
+    if let AnchorState::Foreach(state) = &analysis.state {
+        println!("Anchor (Foreach): {}", analysis.anchor.uuid);
+        println!("  Status: {:?}", state.status);
+        println!("  Items: {}", state.items);
+        if let Some(arg) = analysis.anchor.arguments.arguments.get(0) {
+            println!("  Template: {}", arg.value);
+        }
+    }
+}
+
+fn display_shell_analysis(analysis: &AnchorAnalysis) {
+    if let AnchorState::Shell(state) = &analysis.state {
+        println!("Anchor (Shell): {}", analysis.anchor.uuid);
+        println!("  Status: {:?}", state.status);
+        if let Some(command) = analysis.anchor.parameters.get_as_string_only("command") {
+            println!("  Command: {}", command);
+        }
+        match state.exit_code {
+            Some(code) => println!("  Exit code: {}", code),
+            None => println!("  Exit code: none (terminated by a signal)"),
+        }
+        if !state.stderr.is_empty() {
+            println!("  Stderr: {}", state.stderr.trim_end());
+        }
+    }
+}
+
 fn display_task_analysis(analysis: &AnchorAnalysis) {
     if let AnchorState::Task(state) = &analysis.state {
         println!("Anchor (Task): {}", analysis.anchor.uuid);
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -16,11 +17,42 @@ impl Default for EditorInterface {
     }
 }
 
+/// Prefix of a `provider` value referencing a [`ProviderProfile`] by name.
+pub const PROVIDER_PROFILE_PREFIX: &str = "@";
+
+/// A named provider profile, referenced from contexts as `provider: "@<name>"`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderProfile {
+    /// The provider: a shell command, a native backend object or a fallback chain.
+    pub provider: serde_json::Value,
+    /// Default parameters of the `@answer` tags using the profile (e.g. `timeout`).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub parameters: BTreeMap<String, serde_json::Value>,
+}
+
+/// Limits on the provider calls of an execution; limits left unset are not enforced.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Budget {
+    /// Maximum estimated tokens (input and output) of the answers of a run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens_per_run: Option<u64>,
+    /// Maximum number of provider calls started by a run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_calls_per_run: Option<u64>,
+    /// Maximum length of a single prompt, in characters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_prompt_chars: Option<u64>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ProjectConfig {
     pub editor_interface: EditorInterface,
     pub git_integration_enabled: bool,
     pub aux_paths: Vec<PathBuf>,
+    #[serde(default)]
+    pub providers: BTreeMap<String, ProviderProfile>,
+    #[serde(default)]
+    pub budget: Budget,
 }
 
 impl Default for ProjectConfig {
@@ -29,6 +61,8 @@ impl Default for ProjectConfig {
             editor_interface: EditorInterface::default(),
             git_integration_enabled: true, // Default to true for git integration
             aux_paths: Vec::new(),
+            providers: BTreeMap::new(),
+            budget: Budget::default(),
         }
     }
 }
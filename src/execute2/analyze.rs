@@ -1,9 +1,13 @@
-use super::{tag_answer::AnswerState, tag_inline::InlineState, tag_task::TaskState, Result};
+use super::{
+    tag_answer::AnswerState, tag_foreach::ForeachState, tag_inline::InlineState,
+    tag_shell::ShellState, tag_task::TaskState, Result,
+};
+use crate::agent::usage::{CharTokenizer, TokenUsage, UsageSummary};
 use crate::ast2::{parse_document, Anchor, CommandKind, Content};
 use crate::utils::file::FileAccessor;
 use crate::utils::path::PathResolver;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -13,8 +17,12 @@ use uuid::Uuid;
 /// and holds the state object relevant to that command's execution lifecycle.
 #[derive(Debug)]
 pub enum AnchorState {
-    Answer(AnswerState),
+    Answer(Box<AnswerState>),
+    Derive(Box<AnswerState>),
+    Compact(Box<AnswerState>),
     Inline(InlineState),
+    Foreach(ForeachState),
+    Shell(ShellState),
     Task(TaskState),
 }
 
@@ -73,6 +81,60 @@ pub fn analyze_context(
     analyzer.run(context_name)
 }
 
+/// Aggregates the token usage recorded in the states of all the `@answer` anchors whose
+/// metadata is stored in `metadata_home`.
+///
+/// Answers without a reply are skipped. The usage of answers completed before usage was
+/// recorded is estimated from their query and reply with the default [`CharTokenizer`].
+///
+/// # Errors
+///
+/// Returns an `ExecuteError` if the metadata directory or a state file cannot be read, or
+/// if a state file cannot be deserialized.
+pub fn collect_usage(metadata_home: &Path) -> Result<UsageSummary> {
+    let mut summary = UsageSummary::default();
+    let read_dir = match std::fs::read_dir(metadata_home) {
+        Ok(x) => x,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(summary),
+        Err(e) => return Err(e.into()),
+    };
+    let prefix = format!("{}-", CommandKind::Answer.to_string());
+    for entry in read_dir {
+        let entry = entry?;
+        if !entry.file_name().to_string_lossy().starts_with(&prefix) {
+            continue;
+        }
+        let state_path = entry.path().join("state.json");
+        if !state_path.is_file() {
+            continue;
+        }
+        let state: AnswerState = serde_json::from_str(&std::fs::read_to_string(&state_path)?)?;
+        if state.raw_reply.is_empty() {
+            continue;
+        }
+        let usage = if state.usage == TokenUsage::default() {
+            TokenUsage::estimate(
+                &CharTokenizer::default(),
+                &state.query,
+                &state.raw_reply,
+                None,
+            )
+        } else {
+            state.usage
+        };
+        let provider = match (
+            state.metadata.name.is_empty(),
+            state.metadata.provider.is_empty(),
+        ) {
+            (false, _) => state.metadata.name,
+            (true, false) => state.metadata.provider,
+            (true, true) => "unknown".to_string(),
+        };
+        summary.record(&provider, &usage);
+    }
+    Ok(summary)
+}
+
 /// Internal struct responsible for performing the document analysis.
 ///
 /// It holds references to `FileAccessor` and `PathResolver` to interact with the
@@ -150,12 +212,28 @@ impl Analyzer {
         match anchor.command {
             CommandKind::Answer => {
                 let state = self.load_state::<AnswerState>(anchor.command, &anchor.uuid)?;
-                Ok(Some(AnchorState::Answer(state)))
+                Ok(Some(AnchorState::Answer(Box::new(state))))
+            }
+            CommandKind::Derive => {
+                let state = self.load_state::<AnswerState>(anchor.command, &anchor.uuid)?;
+                Ok(Some(AnchorState::Derive(Box::new(state))))
+            }
+            CommandKind::Compact => {
+                let state = self.load_state::<AnswerState>(anchor.command, &anchor.uuid)?;
+                Ok(Some(AnchorState::Compact(Box::new(state))))
             }
             CommandKind::Inline => {
                 let state = self.load_state::<InlineState>(anchor.command, &anchor.uuid)?;
                 Ok(Some(AnchorState::Inline(state)))
             }
+            CommandKind::Foreach => {
+                let state = self.load_state::<ForeachState>(anchor.command, &anchor.uuid)?;
+                Ok(Some(AnchorState::Foreach(state)))
+            }
+            CommandKind::Shell => {
+                let state = self.load_state::<ShellState>(anchor.command, &anchor.uuid)?;
+                Ok(Some(AnchorState::Shell(state)))
+            }
             CommandKind::Task => {
                 let state = self.load_state::<TaskState>(anchor.command, &anchor.uuid)?;
                 Ok(Some(AnchorState::Task(state)))
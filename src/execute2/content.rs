@@ -5,6 +5,8 @@
 //! `ModelContent`, which aggregates multiple `ModelContentItem`s into a complete prompt.
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::str::FromStr;
 
 /// Represents content originating from the system.
 ///
@@ -168,14 +170,68 @@ impl ToString for ModelContentItem {
 }
 
 /// Defines the various formats in which a prompt can be structured for an external model.
-#[derive(Clone, Copy)]
+///
+/// All formats but `Parts` render the messages returned by [`ModelContent::to_messages`].
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PromptFormat {
     /// Represents a prompt format where each `ModelContentItem` is treated as a distinct "part"
     /// with specific headers (e.g., "System:", "User:", "Assistant:").
     Parts,
+    /// Messages delimited by the ChatML `<|im_start|>` and `<|im_end|>` tokens.
+    ChatMl,
+    /// A JSON array of `{role, content}` objects, as taken by chat completion APIs.
+    Json,
+    /// Messages as `<message role="...">` XML elements, with the text escaped.
+    Xml,
+    /// The text of the messages only, separated by blank lines.
+    Plain,
+    /// Messages delimited by the Llama 3 header and `<|eot_id|>` tokens.
+    Llama3,
+}
+
+impl FromStr for PromptFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "parts" => Ok(PromptFormat::Parts),
+            "chatml" => Ok(PromptFormat::ChatMl),
+            "json" => Ok(PromptFormat::Json),
+            "xml" => Ok(PromptFormat::Xml),
+            "plain" => Ok(PromptFormat::Plain),
+            "llama3" => Ok(PromptFormat::Llama3),
+            _ => Err(format!("unknown prompt format '{}'", s)),
+        }
+    }
+}
+
+/// Defines which content is removed from a prompt exceeding its maximum size.
+///
+/// Only user and agent messages are removed: system messages, like the `prefix`, are
+/// always kept, and so is the latest message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TruncationStrategy {
+    /// Removes the oldest messages first.
+    Oldest,
+    /// Removes the messages in the middle first, keeping the beginning and the end of
+    /// the conversation.
+    MiddleOut,
+}
+
+impl FromStr for TruncationStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "oldest" => Ok(TruncationStrategy::Oldest),
+            "middle" => Ok(TruncationStrategy::MiddleOut),
+            _ => Err(format!("unknown truncation strategy '{}'", s)),
+        }
+    }
 }
 
 /// Configuration for how the `ModelContent` should be formatted into a final prompt string.
+#[derive(Debug, Clone)]
 pub struct PromptConfig {
     /// Optional: The name of the agent to be used in the prompt. If `with_agent_names` is true,
     /// this name will be used to generate a unique agent identifier.
@@ -189,6 +245,40 @@ pub struct PromptConfig {
     pub with_invitation: bool,
 }
 
+/// The role of a single message in a chat-style conversation.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    /// Instructions or context from the system.
+    System,
+    /// Content written by the user.
+    User,
+    /// Content previously generated by the model.
+    Assistant,
+}
+
+impl std::fmt::Display for ChatRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatRole::System => write!(f, "system"),
+            ChatRole::User => write!(f, "user"),
+            ChatRole::Assistant => write!(f, "assistant"),
+        }
+    }
+}
+
+/// A single role-tagged message, as expected by chat completion APIs.
+///
+/// This is the structured counterpart of the `---\nUser:` blocks produced by
+/// [`ModelContent::to_prompt`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    /// The role of the message author.
+    pub role: ChatRole,
+    /// The text of the message.
+    pub content: String,
+}
+
 /// A struct representing a full conversation or a multi-part prompt.
 ///
 /// `ModelContent` is a collection of `ModelContentItem`s, ordered to form a complete
@@ -241,6 +331,89 @@ impl ModelContent {
         self.0.push(item);
     }
 
+    /// Removes user and agent items, as chosen by `strategy`, until the content fits
+    /// `max_size`, as measured by `size`.
+    ///
+    /// A marker telling how many items were removed takes their place; system and merge
+    /// items, and the latest user or agent item, are never removed, so the result can
+    /// still exceed `max_size`.
+    ///
+    /// # Returns
+    ///
+    /// A tuple with the truncated content and the number of removed items.
+    pub fn truncate(
+        &self,
+        max_size: u64,
+        strategy: TruncationStrategy,
+        size: impl Fn(&str) -> u64,
+    ) -> (ModelContent, usize) {
+        let sizes = self
+            .0
+            .iter()
+            .map(|x| size(&x.to_string()))
+            .collect::<Vec<u64>>();
+        let mut total: u64 = sizes.iter().sum();
+        if total <= max_size {
+            return (self.clone(), 0);
+        }
+        let mut candidates = self
+            .0
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| matches!(x, ModelContentItem::User(_) | ModelContentItem::Agent(_)))
+            .map(|(index, _)| index)
+            .collect::<Vec<usize>>();
+        // Always keep the latest message
+        candidates.pop();
+        let order = match strategy {
+            TruncationStrategy::Oldest => candidates,
+            TruncationStrategy::MiddleOut => {
+                // From the middle outwards, alternating sides
+                let middle = candidates.len() / 2;
+                let mut order = Vec::new();
+                for distance in 0..=middle {
+                    if let Some(x) = candidates.get(middle + distance) {
+                        order.push(*x);
+                    }
+                    if distance > 0 {
+                        order.push(candidates[middle - distance]);
+                    }
+                }
+                order
+            }
+        };
+        let mut removed = BTreeSet::new();
+        for index in order {
+            removed.insert(index);
+            total -= sizes[index];
+            if total + size(&Self::truncation_marker(removed.len())) <= max_size {
+                break;
+            }
+        }
+        let first_removed = removed.first().copied();
+        let mut truncated = ModelContent::new();
+        for (index, item) in self.0.iter().enumerate() {
+            if Some(index) == first_removed {
+                truncated.push(ModelContentItem::user(&Self::truncation_marker(
+                    removed.len(),
+                )));
+            }
+            if !removed.contains(&index) {
+                truncated.push(item.clone());
+            }
+        }
+        (truncated, removed.len())
+    }
+
+    /// Returns the marker taking the place of `count` removed items.
+    fn truncation_marker(count: usize) -> String {
+        let messages = if count == 1 { "message" } else { "messages" };
+        format!(
+            "[... {} {} removed to fit the context window ...]\n",
+            count, messages
+        )
+    }
+
     /// Formats a single `ModelContentItem` into a prompt string using the `PromptFormat::Parts` style.
     ///
     /// This internal helper method applies specific formatting rules for System, User, and Agent
@@ -302,34 +475,82 @@ impl ModelContent {
         }
     }
 
-    /// Dispatches to the appropriate embedding function based on the `PromptFormat` specified in the config.
-    ///
-    /// This method acts as a router for different prompt formatting strategies.
-    ///
-    /// # Arguments
-    ///
-    /// * `item` - The `ModelContentItem` to embed.
-    /// * `config` - The `PromptConfig` specifying the desired format.
-    ///
-    /// # Returns
-    ///
-    /// A `String` representing the embedded content item.
-    fn embed_in_prompt(item: &ModelContentItem, config: &PromptConfig) -> String {
-        match config.format {
-            PromptFormat::Parts => Self::embed_in_prompt_as_part(item, config),
+    /// Formats `messages` into a prompt string in one of the message based formats, that is
+    /// any `PromptFormat` but `PromptFormat::Parts`.
+    ///
+    /// With `with_invitation`, the header of an assistant message is appended, where the
+    /// format has one.
+    fn embed_messages_in_prompt(
+        messages: &[ChatMessage],
+        format: PromptFormat,
+        with_invitation: bool,
+    ) -> String {
+        let mut prompt = String::new();
+        match format {
+            PromptFormat::Parts => panic!("parts are not embedded as messages, bug!"),
+            PromptFormat::Json => {
+                return serde_json::to_string_pretty(messages).expect("messages serialize to JSON")
+            }
+            PromptFormat::ChatMl => {
+                for message in messages {
+                    prompt.push_str(&format!(
+                        "<|im_start|>{}\n{}<|im_end|>\n",
+                        message.role, message.content
+                    ));
+                }
+                if with_invitation {
+                    prompt.push_str("<|im_start|>assistant\n");
+                }
+            }
+            PromptFormat::Xml => {
+                for message in messages {
+                    let content = message
+                        .content
+                        .replace('&', "&amp;")
+                        .replace('<', "&lt;")
+                        .replace('>', "&gt;");
+                    prompt.push_str(&format!(
+                        "<message role=\"{}\">\n{}\n</message>\n",
+                        message.role, content
+                    ));
+                }
+                if with_invitation {
+                    prompt.push_str("<message role=\"assistant\">\n");
+                }
+            }
+            PromptFormat::Plain => {
+                prompt = messages
+                    .iter()
+                    .map(|message| format!("{}\n", message.content))
+                    .collect::<Vec<String>>()
+                    .join("\n");
+            }
+            PromptFormat::Llama3 => {
+                prompt.push_str("<|begin_of_text|>");
+                for message in messages {
+                    prompt.push_str(&format!(
+                        "<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>",
+                        message.role, message.content
+                    ));
+                }
+                if with_invitation {
+                    prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+                }
+            }
         }
+        prompt
     }
 
-    /// Converts the entire `ModelContent` into a single formatted prompt string.
+    /// Normalizes the `ModelContent` into the list of messages actually sent to a model.
     ///
-    /// This method iterates through all `ModelContentItem`s and formats each one
-    /// using `ModelContentItem::to_prompt()`, then joins them with newline characters.
-    /// The result is a complete prompt ready for an external model.
+    /// The identity message (when agent names are enabled) is prepended, empty items are
+    /// dropped, `MergeDownstream` / `MergeUpstream` items are folded into their neighbours
+    /// and consecutive messages of the same kind are merged together.
     ///
     /// # Returns
     ///
-    /// A `String` representing the concatenated and formatted prompt.
-    pub fn to_prompt(&self, config: &PromptConfig) -> String {
+    /// A tuple with the merged items and the generated agent name, if any.
+    fn merge_items(&self, config: &PromptConfig) -> (Vec<ModelContentItem>, Option<String>) {
         let agent_name = match config.with_agent_names {
             false => None,
             true => config
@@ -450,9 +671,66 @@ impl ModelContent {
             final_merged_items.push(last);
         }
 
+        (final_merged_items, agent_name)
+    }
+
+    /// Converts the entire `ModelContent` into a list of role-tagged chat messages.
+    ///
+    /// This is the structured counterpart of [`ModelContent::to_prompt`], meant for
+    /// providers that accept a messages array instead of a flattened prompt.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<ChatMessage>` with one message per merged content item.
+    pub fn to_messages(&self, config: &PromptConfig) -> Vec<ChatMessage> {
+        let (items, _) = self.merge_items(config);
+        items
+            .iter()
+            .filter_map(|item| match item {
+                ModelContentItem::System(content) => Some(ChatMessage {
+                    role: ChatRole::System,
+                    content: content.text.trim().to_string(),
+                }),
+                ModelContentItem::User(content) => Some(ChatMessage {
+                    role: ChatRole::User,
+                    content: content.text.trim().to_string(),
+                }),
+                ModelContentItem::Agent(content) => Some(ChatMessage {
+                    role: ChatRole::Assistant,
+                    content: content.text.trim().to_string(),
+                }),
+                _ => None,
+            })
+            .filter(|message| !message.content.is_empty())
+            .collect()
+    }
+
+    /// Converts the entire `ModelContent` into a single formatted prompt string.
+    ///
+    /// With `PromptFormat::Parts`, this method iterates through all `ModelContentItem`s and
+    /// formats each one using `ModelContent::embed_in_prompt_as_part()`, then joins them
+    /// with newline characters. The other formats render the messages returned by
+    /// [`ModelContent::to_messages`]. The result is a complete prompt ready for an
+    /// external model.
+    ///
+    /// # Returns
+    ///
+    /// A `String` representing the concatenated and formatted prompt.
+    pub fn to_prompt(&self, config: &PromptConfig) -> String {
+        if config.format != PromptFormat::Parts {
+            let messages = self.to_messages(config);
+            return Self::embed_messages_in_prompt(
+                &messages,
+                config.format,
+                config.with_invitation,
+            );
+        }
+
+        let (final_merged_items, agent_name) = self.merge_items(config);
+
         let mut prompt = final_merged_items
             .iter()
-            .map(|item| Self::embed_in_prompt(item, config))
+            .map(|item| Self::embed_in_prompt_as_part(item, config))
             .collect::<Vec<String>>()
             .join("\n");
 
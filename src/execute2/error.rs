@@ -105,6 +105,20 @@ pub enum ExecuteError {
     #[error("Shell call error: {0}")]
     ShellError(String),
 
+    /// An error returned by a native (non-shell) model provider.
+    ///
+    /// This captures failures when talking to a provider endpoint directly, such as
+    /// connection errors or non-success HTTP responses.
+    #[error("Provider call error: {0}")]
+    ProviderError(String),
+
+    /// Indicates that a query has no recorded reply in the cassette being replayed.
+    ///
+    /// Replay mode never calls a provider, so every query must have been recorded
+    /// beforehand; the payload is the normalized hash of the missing query.
+    #[error("No reply recorded in cassette for query hash '{0}'")]
+    CassetteMiss(String),
+
     /// An error occurring during the resolution of a file system path.
     ///
     /// This can happen if a path is malformed, inaccessible, or cannot be converted
@@ -213,6 +227,42 @@ pub enum ExecuteError {
     #[error("Missing 'choice' parameter at {range:?}")]
     MissingChoice { range: Range },
 
+    /// Indicates that the `@foreach` tag is missing the template argument.
+    #[error("Missing argument for '@foreach' tag at {range:?}")]
+    MissingForeachArgument { range: Range },
+
+    /// Indicates that the `@foreach` tag has none of the `items`, `glob` and `lines` parameters.
+    #[error("Missing 'items', 'glob' or 'lines' parameter for '@foreach' tag at {range:?}")]
+    MissingForeachItems { range: Range },
+
+    /// Indicates that the `@file` tag has neither the file name argument nor the `glob` parameter.
+    #[error("Missing argument or 'glob' parameter for '@file' tag at {range:?}")]
+    MissingFileArgument { range: Range },
+
+    /// Indicates that a tag, like `@file`, names a file outside the project, or inside `.vespe`.
+    #[error("Cannot read '{file}', it must be a project file outside '.vespe'")]
+    FileOutsideProject { file: String },
+
+    /// Indicates that the `@derive` tag has none of the `context`, `anchor` and `file` parameters.
+    #[error("Missing 'context', 'anchor' or 'file' parameter for '@derive' tag at {range:?}")]
+    MissingDeriveSource { range: Range },
+
+    /// Indicates that the source anchor of the `@derive` tag is not in the context.
+    #[error("Anchor '{uuid}' derived at {range:?} not found")]
+    DeriveAnchorNotFound { uuid: String, range: Range },
+
+    /// Indicates an `@else` tag outside a conditional section, or a second one in it.
+    #[error("'@else' without a matching '@if' at {range:?}")]
+    UnmatchedElse { range: Range },
+
+    /// Indicates an `@endif` tag outside a conditional section.
+    #[error("'@endif' without a matching '@if' at {range:?}")]
+    UnmatchedEndIf { range: Range },
+
+    /// Indicates that a conditional section is not closed by `@endif`.
+    #[error("'@if' without a matching '@endif' at {range:?}")]
+    UnterminatedIf { range: Range },
+
     /// An error originating from the utility module.
     #[error("Utility error: {0}")]
     UtilError(#[from] crate::utils::Error),
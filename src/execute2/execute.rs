@@ -5,10 +5,19 @@
 //! a multi-pass execution strategy to handle dynamic content generation and
 //! modification.
 use super::{ExecuteError, Result};
+use crate::agent::cache::ResponseCache;
+use crate::agent::cassette::{Cassette, CassetteMode};
+use crate::agent::provider::{
+    ProviderAttempt, ProviderRegistry, ProviderRequest, ProviderResponse,
+};
+use crate::agent::shell::{PROMPT_FILE_PLACEHOLDER, PROMPT_PLACEHOLDER};
+use crate::agent::usage::{CharTokenizer, Tokenizer, UsageTally, DEFAULT_CHARS_PER_TOKEN};
 use crate::ast2::{
     Anchor, AnchorKind, CommandKind, Content, JsonPlusEntity, JsonPlusObject, Parameters, Range,
     Tag,
 };
+use crate::config::{Budget, ProviderProfile, PROVIDER_PROFILE_PREFIX};
+use crate::constants::CTX_DIR_NAME;
 use crate::execute2::content::{ModelContent, ModelContentItem, PromptConfig, PromptFormat};
 use crate::execute2::tag_answer::AnswerStatus;
 use crate::execute2::tags::TagBehaviorDispatch;
@@ -19,8 +28,10 @@ use std::sync::mpsc;
 
 use handlebars::Handlebars;
 use sha2::{Digest, Sha256};
-use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use uuid::Uuid;
 
 const TAB_SIZE: usize = 4;
@@ -43,10 +54,53 @@ pub fn execute_context(
     path_res: Arc<dyn PathResolver>,
     context_name: &str,
     data: Option<&JsonPlusObject>,
+) -> Result<ModelContent> {
+    execute_context_with_options(
+        file_access,
+        path_res,
+        context_name,
+        data,
+        ExecuteOptions::default(),
+    )
+}
+
+/// Options tuning how a context is executed.
+#[derive(Debug, Clone, Default)]
+pub struct ExecuteOptions {
+    /// Cassette to record provider replies into, or to replay them from.
+    pub cassette: Option<Arc<Cassette>>,
+    /// Cache answering identical queries without calling providers, when enabled.
+    pub cache: Option<Arc<ResponseCache>>,
+    /// Named provider profiles, referenced as `provider: "@<name>"`.
+    pub provider_profiles: BTreeMap<String, ProviderProfile>,
+    /// Tokenizer estimating the tokens of answers; a [`CharTokenizer`] if not given.
+    pub tokenizer: Option<Arc<dyn Tokenizer>>,
+    /// Tally accounting the usage of the execution; a private one is used if not given.
+    pub usage: Option<Arc<UsageTally>>,
+    /// Limits on the provider calls of the execution, overridable by parameters.
+    pub budget: Budget,
+}
+
+/// Executes a context like [`execute_context`], with the given [`ExecuteOptions`].
+///
+/// # Arguments
+/// * `file_access` - A thread-safe file accessor.
+/// * `path_res` - A thread-safe path resolver.
+/// * `context_name` - The name of the root context to execute.
+/// * `options` - The options tuning the execution.
+///
+/// # Returns
+/// The final, collected `ModelContent` after full execution.
+pub fn execute_context_with_options(
+    file_access: Arc<dyn FileAccessor>,
+    path_res: Arc<dyn PathResolver>,
+    context_name: &str,
+    data: Option<&JsonPlusObject>,
+    options: ExecuteOptions,
 ) -> Result<ModelContent> {
     tracing::debug!("Executing context: {}", context_name);
 
-    let exe = Worker::new(file_access, path_res);
+    let exe = Worker::new(file_access, path_res, options);
     exe.execute(context_name, data)
 }
 
@@ -71,11 +125,22 @@ pub fn collect_context(
 ) -> Result<ModelContent> {
     tracing::debug!("Collecting context: {}", context_name);
 
-    let exe = Worker::new(file_access, path_res);
+    let exe = Worker::new(file_access, path_res, ExecuteOptions::default());
 
     exe.collect(context_name, data)
 }
 
+/// A conditional section opened by `@if`.
+#[derive(Clone, Debug)]
+struct ConditionFrame {
+    /// Whether the current branch of the section is taken.
+    taken: bool,
+    /// Whether the `@else` branch has been entered.
+    in_else: bool,
+    /// The range of the `@if` tag opening the section.
+    range: Range,
+}
+
 /// Central state object for the execution engine.
 ///
 /// The `Collector` accumulates the final `ModelContent` and tracks execution-time
@@ -98,6 +163,16 @@ pub(crate) struct Collector {
     /// Execution-time default parameters for tags. These parameters can be
     /// inherited or overridden by individual tags.
     default_parameters: Parameters,
+    /// Execution-time variables, stored by tags with a `store` parameter. They are
+    /// rendered into the subsequent user content and into the `data` of included contexts.
+    variables: JsonPlusObject,
+    /// The data the current context file is executed with, i.e. the defines and arguments
+    /// of the execution or the `data` of an `@include`. The defines and arguments, named
+    /// with a `$` prefix, are inherited by included context files.
+    data: JsonPlusObject,
+    /// A stack of the conditional sections opened by `@if` in the current context file;
+    /// each entry tells whether its current branch is taken.
+    conditions: Vec<ConditionFrame>,
     /// The latest processed range in the document. Used primarily for error reporting
     /// to pinpoint the location of issues.
     latest_range: Range,
@@ -226,6 +301,15 @@ impl Collector {
         &self.anchor_stack
     }
 
+    /// Returns the path of the context file being processed, if any.
+    ///
+    /// # Returns
+    ///
+    /// The last entry of the visit stack, i.e. the innermost included context.
+    pub fn current_context(&self) -> Option<&Path> {
+        self.visit_stack.last().map(|x| x.as_path())
+    }
+
     /// Checks if the collector is currently inside an anchor of a specific `CommandKind`.
     ///
     /// This method iterates through the `anchor_stack` to determine if any active
@@ -262,6 +346,9 @@ impl Collector {
             context: ModelContent::new(),
             context_hasher: Sha256::new(),
             default_parameters: Parameters::new(),
+            variables: JsonPlusObject::new(),
+            data: JsonPlusObject::new(),
+            conditions: Vec::new(),
             latest_range: Range::null(),
             latest_task: None,
             latest_agent_hash: None,
@@ -296,6 +383,9 @@ impl Collector {
             context: self.context.clone(),
             context_hasher: self.context_hasher.clone(),
             default_parameters: self.default_parameters.clone(),
+            variables: self.variables.clone(),
+            data: self.defines(),
+            conditions: Vec::new(),
             latest_range: Range::null(),
             latest_task: None,
             latest_agent_hash: None,
@@ -305,7 +395,7 @@ impl Collector {
     /// Ascends from a nested context, merging the state from the descended collector.
     ///
     /// This method takes the `Collector` that was used for a sub-context (the `descent_collector`)
-    /// and merges its accumulated `context` and `default_parameters` back into the
+    /// and merges its accumulated `context`, `default_parameters` and `variables` back into the
     /// current collector. The `visit_stack` is implicitly handled by the `descent` method.
     ///
     /// # Arguments
@@ -320,6 +410,7 @@ impl Collector {
         self.context = descent_collector.context;
         self.context_hasher = descent_collector.context_hasher;
         self.default_parameters = descent_collector.default_parameters;
+        self.variables = descent_collector.variables;
         self
     }
 
@@ -435,6 +526,140 @@ impl Collector {
         self
     }
 
+    /// Returns the variables stored so far.
+    ///
+    /// # Returns
+    ///
+    /// A reference to the [`JsonPlusObject`] mapping variable names to values.
+    pub fn variables(&self) -> &JsonPlusObject {
+        &self.variables
+    }
+
+    /// Stores `value` in the variable `name`, replacing any previous value.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the variable.
+    /// * `value` - The new value of the variable.
+    ///
+    /// # Returns
+    ///
+    /// The `Collector` with the updated variables.
+    pub fn set_variable(mut self, name: &str, value: JsonPlusEntity) -> Self {
+        self.variables.insert(name.to_string(), value);
+        self
+    }
+
+    /// Sets the data the current context file is executed with.
+    ///
+    /// The defines and arguments inherited from the including context are kept, unless
+    /// overridden by the given data.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data of the context file, if any.
+    ///
+    /// # Returns
+    ///
+    /// The `Collector` with the updated data.
+    pub fn set_data(mut self, data: Option<&JsonPlusObject>) -> Self {
+        let mut merged = self.defines();
+        if let Some(data) = data {
+            merged.properties.extend(data.properties.clone());
+        }
+        self.data = merged;
+        self
+    }
+
+    /// Returns the defines and arguments of the execution, i.e. the entries of the data
+    /// named with a `$` prefix.
+    fn defines(&self) -> JsonPlusObject {
+        JsonPlusObject::from_map(
+            self.data
+                .properties
+                .iter()
+                .filter(|(key, _)| key.starts_with('$'))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        )
+    }
+
+    /// Looks up a value by name, as conditions of `@if` do.
+    ///
+    /// Stored variables are looked up first, then the data of the context file, where
+    /// defines and arguments of the execution are named with a `$` prefix.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the value, with or without the `$` prefix.
+    ///
+    /// # Returns
+    ///
+    /// The value, or `None` if there is no value with that name.
+    pub fn lookup(&self, name: &str) -> Option<&JsonPlusEntity> {
+        self.variables
+            .get(name)
+            .or_else(|| self.data.get(name))
+            .or_else(|| self.data.get(&format!("${}", name)))
+    }
+
+    /// Returns whether the content being processed is in a branch not taken of a
+    /// conditional section, and must be neither collected nor executed.
+    pub fn is_skipping(&self) -> bool {
+        self.conditions.iter().any(|x| !x.taken)
+    }
+
+    /// Opens a conditional section, as `@if` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `taken` - Whether the first branch of the section is taken.
+    ///
+    /// # Returns
+    ///
+    /// The `Collector` with the new section.
+    pub fn open_condition(mut self, taken: bool) -> Self {
+        self.conditions.push(ConditionFrame {
+            taken,
+            in_else: false,
+            range: self.latest_range,
+        });
+        self
+    }
+
+    /// Enters the alternative branch of the innermost conditional section, as `@else` does.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::UnmatchedElse`] if there is no open section, or if its
+    /// alternative branch has already been entered.
+    pub fn else_condition(mut self) -> Result<Self> {
+        match self.conditions.last_mut() {
+            Some(frame) if !frame.in_else => {
+                frame.taken = !frame.taken;
+                frame.in_else = true;
+                Ok(self)
+            }
+            _ => Err(ExecuteError::UnmatchedElse {
+                range: self.latest_range,
+            }),
+        }
+    }
+
+    /// Closes the innermost conditional section, as `@endif` does.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::UnmatchedEndIf`] if there is no open section.
+    pub fn close_condition(mut self) -> Result<Self> {
+        match self.conditions.pop() {
+            Some(_) => Ok(self),
+            None => Err(ExecuteError::UnmatchedEndIf {
+                range: self.latest_range,
+            }),
+        }
+    }
+
     /// Returns the latest task anchor encountered by the collector.
     ///
     /// # Returns
@@ -491,7 +716,12 @@ impl Collector {
 pub(crate) struct Worker {
     file_access: Arc<dyn FileAccessor>,
     path_res: Arc<dyn PathResolver>,
-    task_manager: TaskManager<String, String, String>,
+    task_manager: TaskManager<ProviderResponse, Vec<ProviderAttempt>, String>,
+    providers: Arc<ProviderRegistry>,
+    usage: Arc<UsageTally>,
+    /// The anchors whose command already ran in this execution, e.g. of `@shell`.
+    commands_run: Arc<Mutex<HashSet<Uuid>>>,
+    options: ExecuteOptions,
 }
 
 impl Worker {
@@ -503,15 +733,24 @@ impl Worker {
     ///                   used for all file system operations.
     /// * `path_res` - A shared reference to an object implementing `PathResolver`,
     ///                used for resolving context and metadata paths.
+    /// * `options` - The [`ExecuteOptions`] tuning the execution.
     ///
     /// # Returns
     ///
     /// A new `Worker` instance.
-    fn new(file_access: Arc<dyn FileAccessor>, path_res: Arc<dyn PathResolver>) -> Self {
+    fn new(
+        file_access: Arc<dyn FileAccessor>,
+        path_res: Arc<dyn PathResolver>,
+        options: ExecuteOptions,
+    ) -> Self {
         Worker {
+            providers: Arc::new(ProviderRegistry::for_project(path_res.project_root())),
             file_access,
             path_res,
             task_manager: TaskManager::new(),
+            usage: options.usage.clone().unwrap_or_default(),
+            commands_run: Arc::new(Mutex::new(HashSet::new())),
+            options,
         }
     }
 
@@ -584,6 +823,27 @@ impl Worker {
         }
     }
 
+    /// Collects a context's content as it is, in a single read-only pass, even if the
+    /// context still needs to be executed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::PathResolutionError`] if the context cannot be resolved.
+    /// Returns other [`ExecuteError`] variants for failures while collecting.
+    fn collect_as_is(
+        &self,
+        context_name: &str,
+        data: Option<&JsonPlusObject>,
+    ) -> Result<ModelContent> {
+        let context_path = self.path_res.resolve_input_file(context_name)?;
+        let collector = match Collector::new().descent(&context_path) {
+            Some(collector) => collector,
+            None => return Ok(ModelContent::new()),
+        };
+        let (_, collector) = self.collect_pass(collector, &context_path, data)?;
+        Ok(collector.context().clone())
+    }
+
     /// Internal execution loop for processing a context, potentially over multiple passes.
     ///
     /// This function drives the multi-pass execution. It repeatedly calls `execute_pass`
@@ -811,20 +1071,170 @@ impl Worker {
         }
     }
 
-    pub fn craft_prompt(
-        &self,
-        agent_hash: Option<String>,
-        parameters: &Parameters,
-        prompt: &ModelContent,
-    ) -> Result<String> {
-        let prompt_config = PromptConfig {
+    /// Reads the prompt configuration from `parameters`; the format is given by
+    /// `prompt_format`, `parts` by default.
+    fn prompt_config(agent_hash: Option<String>, parameters: &Parameters) -> Result<PromptConfig> {
+        let format = match parameters.get("prompt_format") {
+            None => PromptFormat::Parts,
+            Some(x) => x
+                .as_string_only()
+                .ok_or_else(|| format!("{:?}", x))
+                .and_then(|x| x.parse::<PromptFormat>())
+                .map_err(|e| {
+                    ExecuteError::UnsupportedParameterValue(format!("prompt_format: {}", e))
+                })?,
+        };
+        Ok(PromptConfig {
             agent: agent_hash,
-            format: PromptFormat::Parts,
+            format,
             with_agent_names: parameters.get_as_bool("with_agent_names").unwrap_or(false),
             with_invitation: parameters.get_as_bool("with_invitation").unwrap_or(false),
+        })
+    }
+
+    /// Bundles `prompt` and `parameters` into the request handed to a [`Provider`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::UnsupportedParameterValue`] if `prompt_format` is not a
+    /// known format.
+    ///
+    /// [`Provider`]: crate::agent::provider::Provider
+    pub fn craft_provider_request(
+        &self,
+        agent_hash: Option<String>,
+        parameters: &Parameters,
+        prompt: ModelContent,
+        timeout: Option<Duration>,
+    ) -> Result<ProviderRequest> {
+        Ok(ProviderRequest {
+            content: prompt,
+            prompt_config: Self::prompt_config(agent_hash, parameters)?,
+            parameters: parameters.clone(),
+            timeout,
+            environment: BTreeMap::new(),
+        })
+    }
+
+    /// Returns the registry used to resolve `provider` parameters.
+    pub fn providers(&self) -> &ProviderRegistry {
+        &self.providers
+    }
+
+    /// Expands the references to provider profiles in the `provider` parameter.
+    ///
+    /// When `provider` is a single reference (`"@<name>"`) it is replaced by the provider of
+    /// the profile, and the parameters of the profile are integrated as defaults. References
+    /// inside a fallback chain are replaced by the provider of their profile only.
+    pub fn integrate_provider_profile(&self, parameters: &Parameters) -> Result<Parameters> {
+        let provider = match parameters.get("provider") {
+            Some(x) => x,
+            None => return Ok(parameters.clone()),
+        };
+        let mut integrated = parameters.clone();
+        match self.provider_profile(provider)? {
+            Some(profile) => {
+                integrated.insert("provider".to_string(), (&profile.provider).into());
+                let mut defaults = Parameters::new();
+                for (key, value) in &profile.parameters {
+                    defaults.insert(key.clone(), value.into());
+                }
+                integrated = integrated.integrate(&defaults);
+            }
+            None => {
+                if let JsonPlusEntity::Array(chain) = provider {
+                    let mut expanded = Vec::new();
+                    for x in chain {
+                        match self.provider_profile(x)?.map(|p| (&p.provider).into()) {
+                            Some(JsonPlusEntity::Array(y)) => expanded.extend(y),
+                            Some(y) => expanded.push(y),
+                            None => expanded.push(x.clone()),
+                        }
+                    }
+                    integrated.insert("provider".to_string(), JsonPlusEntity::Array(expanded));
+                }
+            }
+        }
+        Ok(integrated)
+    }
+
+    /// Returns the profile referenced by a provider value, if it is a reference.
+    fn provider_profile(&self, provider: &JsonPlusEntity) -> Result<Option<&ProviderProfile>> {
+        let name = match provider.as_string_only() {
+            Some(x) => match x.strip_prefix(PROVIDER_PROFILE_PREFIX) {
+                Some(name) => name.trim().to_string(),
+                None => return Ok(None),
+            },
+            None => return Ok(None),
         };
-        let prompt = prompt.to_prompt(&prompt_config);
-        Ok(prompt)
+        match self.options.provider_profiles.get(&name) {
+            Some(profile) => Ok(Some(profile)),
+            None => Err(ExecuteError::UnsupportedParameterValue(format!(
+                "provider: unknown provider profile '{}'",
+                name
+            ))),
+        }
+    }
+
+    /// Returns the cassette provider replies are recorded into or replayed from, if any.
+    pub fn cassette(&self) -> Option<&Cassette> {
+        self.options.cassette.as_deref()
+    }
+
+    /// Records `raw_reply` as the reply to `query` into the cassette, if recording.
+    ///
+    /// The cassette is written through the file accessor, as the state files are.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::Generic`] if the cassette cannot be saved.
+    pub fn record_in_cassette(&self, query: &str, raw_reply: &str) -> Result<()> {
+        match self.cassette() {
+            Some(cassette) if cassette.mode() == CassetteMode::Record => cassette
+                .record(
+                    &Collector::normalized_hash(query),
+                    raw_reply,
+                    self.file_access.as_ref(),
+                )
+                .map_err(|e| ExecuteError::Generic(e.to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the cache of provider replies, if caching is enabled.
+    pub fn response_cache(&self) -> Option<&ResponseCache> {
+        self.options.cache.as_deref()
+    }
+
+    /// Returns the tokenizer estimating the tokens of answers.
+    pub fn tokenizer(&self) -> &dyn Tokenizer {
+        match &self.options.tokenizer {
+            Some(tokenizer) => tokenizer.as_ref(),
+            None => &CharTokenizer {
+                chars_per_token: DEFAULT_CHARS_PER_TOKEN,
+            },
+        }
+    }
+
+    /// Returns the tally accounting the usage of the execution.
+    pub fn usage_tally(&self) -> &UsageTally {
+        &self.usage
+    }
+
+    /// Records that the command of the anchor `uuid` runs in this execution.
+    ///
+    /// Returns `false` if it already ran, so that a command runs at most once per
+    /// execution, however many passes it takes.
+    pub fn record_command_run(&self, uuid: &Uuid) -> bool {
+        self.commands_run
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(*uuid)
+    }
+
+    /// Returns the budget of the execution, as given by [`ExecuteOptions::budget`].
+    pub fn budget(&self) -> &Budget {
+        &self.options.budget
     }
 
     /// Executes a single read-only pass over the context file.
@@ -929,8 +1339,13 @@ impl Worker {
         };
         let ast = crate::ast2::parse_document(&document)?;
         let anchor_index = super::utils::AnchorIndex::new(&ast.content);
+        collector = collector.set_data(data);
 
         for item in &ast.content {
+            // Skip everything in branches not taken, except what delimits them
+            if collector.is_skipping() && !Self::is_condition_tag(item) {
+                continue;
+            }
             let (do_next_pass, next_collector, patches) = match item {
                 Content::Comment(_) => {
                     // Ignore comments
@@ -955,9 +1370,17 @@ impl Worker {
                             collector = collector
                                 .push_item(ModelContentItem::agent(agent_hash, &text.content));
                         }
+                    } else if collector
+                        .is_in_this_kind_of_anchor(CommandKind::Shell)
+                        .is_some()
+                    {
+                        // Command output, neither rendered nor parsed for tags
+                        collector = collector.push_item(ModelContentItem::user(&text.content));
                     } else {
                         // User writes outside answer anchors
-                        collector = collector.push_item(ModelContentItem::user(&text.content));
+                        let content =
+                            self.render_variables(&text.content, collector.variables())?;
+                        collector = collector.push_item(ModelContentItem::user(&content));
                     }
                     (false, collector, vec![])
                 }
@@ -1066,10 +1489,24 @@ impl Worker {
                 return Ok((true, collector));
             }
         }
+        if let Some(frame) = collector.conditions.last() {
+            return Err(ExecuteError::UnterminatedIf { range: frame.range });
+        }
         // No patches applied nor new pass triggered, then return definitive collector
         Ok((false, collector))
     }
 
+    /// Returns whether `item` is an `@if`, `@else` or `@endif` tag.
+    fn is_condition_tag(item: &Content) -> bool {
+        match item {
+            Content::Tag(tag) => matches!(
+                tag.command,
+                CommandKind::If | CommandKind::Else | CommandKind::EndIf
+            ),
+            _ => false,
+        }
+    }
+
     /// Applies a series of text patches to the given content.
     ///
     /// Patches are applied in reverse order to ensure that `Range`s remain valid
@@ -1498,6 +1935,103 @@ impl Worker {
         self.read_context_from_path(&self.path_res.resolve_input_file(context_name)?)
     }
 
+    /// Reads a file resolved like a context name, e.g. a schema referenced by a tag.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::PathResolutionError`] if the file name cannot be resolved.
+    /// Returns [`ExecuteError::IoError`] if the file cannot be read.
+    pub fn read_input_file(&self, file_name: &str) -> Result<String> {
+        self.read_file(&self.path_res.resolve_input_file(file_name)?)
+    }
+
+    /// Returns the root directory of the project.
+    pub fn project_root(&self) -> PathBuf {
+        self.path_res.project_root()
+    }
+
+    /// Reads a project file, named relative to the project root.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::FileOutsideProject`] if the file name is absolute, leaves
+    /// the project root or points into the `.vespe` directory.
+    /// Returns [`ExecuteError::IoError`] if the file cannot be read.
+    pub fn read_project_file(&self, file_name: &str) -> Result<String> {
+        Self::check_project_file_name(file_name)?;
+        self.read_file(&self.path_res.project_root().join(file_name))
+    }
+
+    /// Checks that `file_name`, or a glob pattern, names project files outside the
+    /// `.vespe` directory.
+    fn check_project_file_name(file_name: &str) -> Result<()> {
+        let path = Path::new(file_name);
+        let is_outside = path.is_absolute()
+            || path
+                .components()
+                .any(|x| !matches!(x, Component::Normal(_) | Component::CurDir))
+            || path
+                .components()
+                .find(|x| !matches!(x, Component::CurDir))
+                .is_some_and(|x| x.as_os_str() == CTX_DIR_NAME);
+        if is_outside {
+            return Err(ExecuteError::FileOutsideProject {
+                file: file_name.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the names of the project files matching the glob `pattern`.
+    ///
+    /// Both the pattern and the names are relative to the project root; names use `/` as
+    /// separator and are sorted. Hidden files and directories match only if the pattern
+    /// names them explicitly.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::FileOutsideProject`] if the pattern is absolute, leaves the
+    /// project root or points into the `.vespe` directory.
+    /// Returns [`ExecuteError::UnsupportedParameterValue`] if the pattern is invalid.
+    /// Returns [`ExecuteError::IoError`] if a directory cannot be read.
+    pub fn glob_project_files(&self, pattern: &str) -> Result<Vec<String>> {
+        Self::check_project_file_name(pattern)?;
+        let root = self.path_res.project_root();
+        let full_pattern = format!(
+            "{}/{}",
+            glob::Pattern::escape(&root.to_string_lossy()),
+            pattern
+        );
+        let options = glob::MatchOptions {
+            require_literal_leading_dot: true,
+            ..Default::default()
+        };
+        let paths = glob::glob_with(&full_pattern, options).map_err(|e| {
+            ExecuteError::UnsupportedParameterValue(format!("glob: {}: {}", pattern, e))
+        })?;
+        let mut names = Vec::new();
+        for path in paths {
+            let path = path.map_err(|e| ExecuteError::IoError(e.into()))?;
+            if !path.is_file() {
+                continue;
+            }
+            // The prefix is stripped lexically, keep only names below the root
+            let relative = match path.strip_prefix(&root) {
+                Ok(x) if x.components().all(|x| matches!(x, Component::Normal(_))) => x,
+                _ => continue,
+            };
+            names.push(
+                relative
+                    .components()
+                    .map(|x| x.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+            );
+        }
+        names.sort();
+        Ok(names)
+    }
+
     /// Reads the raw content of a context file from a given path.
     ///
     /// This is a utility function that reads the entire content of a file
@@ -1519,10 +2053,74 @@ impl Worker {
         Ok(context_content)
     }
 
+    /// Builds the Handlebars registry used to render contexts.
+    ///
+    /// Placeholders without a value are rendered empty, except those named in `kept`, or
+    /// all of them if `kept` is not given, that are kept as they are so that they can be
+    /// filled later, e.g. by variables stored during the execution or by the prompt
+    /// placeholders of provider commands.
+    fn handlebars(kept: Option<BTreeSet<String>>) -> Handlebars<'static> {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_helper(
+            "helperMissing",
+            Box::new(
+                move |h: &handlebars::Helper<'_>,
+                      _: &Handlebars<'_>,
+                      _: &handlebars::Context,
+                      _: &mut handlebars::RenderContext<'_, '_>,
+                      out: &mut dyn handlebars::Output|
+                      -> handlebars::HelperResult {
+                    if kept.as_ref().is_some_and(|x| !x.contains(h.name())) {
+                        return Ok(());
+                    }
+                    let mut placeholder = h.name().to_string();
+                    for param in h.params() {
+                        placeholder.push(' ');
+                        match param.relative_path() {
+                            Some(path) => placeholder.push_str(path),
+                            None => placeholder.push_str(&param.value().to_string()),
+                        }
+                    }
+                    out.write(&format!("{{{{{}}}}}", placeholder))?;
+                    Ok(())
+                },
+            ),
+        );
+        handlebars
+    }
+
+    /// Returns the names of the placeholders that must survive the rendering of `context`
+    /// with its data: the prompt placeholders of provider commands and the variables
+    /// stored by the tags of `context`.
+    fn kept_placeholders(context: &str) -> BTreeSet<String> {
+        let mut kept = [PROMPT_PLACEHOLDER, PROMPT_FILE_PLACEHOLDER]
+            .iter()
+            .map(|x| x.trim_matches(|c| c == '{' || c == '}').to_string())
+            .collect::<BTreeSet<String>>();
+        // A document not parsing here fails later, when parsed after rendering
+        if let Ok(ast) = crate::ast2::parse_document(context) {
+            for item in &ast.content {
+                let parameters = match item {
+                    Content::Tag(tag) => &tag.parameters,
+                    Content::Anchor(anchor) if anchor.kind == AnchorKind::Begin => {
+                        &anchor.parameters
+                    }
+                    _ => continue,
+                };
+                if let Some(name) = parameters.get_as_string_only("store") {
+                    kept.insert(name);
+                }
+            }
+        }
+        kept
+    }
+
     /// Processes the given context string using Handlebars templating with provided data.
     ///
     /// This allows for dynamic injection of data into the context content before
-    /// further AST parsing and execution.
+    /// further AST parsing and execution. Placeholders missing from `data` are rendered
+    /// empty, except the ones filled later (see [`Worker::kept_placeholders`]), and the
+    /// content of verbatim anchors, e.g. the output of `@shell`, is kept as it is.
     ///
     /// # Arguments
     ///
@@ -1541,10 +2139,84 @@ impl Worker {
         context: String,
         data: &JsonPlusObject,
     ) -> Result<String> {
-        let handlebars = Handlebars::new();
         let data: serde_json::Value = data.into();
+        let handlebars = Self::handlebars(Some(Self::kept_placeholders(&context)));
+        let ranges = crate::ast2::find_verbatim_ranges(&context);
+        if ranges.is_empty() {
+            return Ok(handlebars.render_template(&context, &data)?);
+        }
+        // Render with the verbatim content replaced by numbered placeholders
+        let mut verbatim = Vec::new();
+        let mut patches = Vec::new();
+        for (i, range) in ranges.into_iter().enumerate() {
+            verbatim.push(Self::get_range(&context, &range)?.to_string());
+            patches.push((range, format!("\0{}\0", i)));
+        }
+        let context = Self::apply_patches(&context, patches)?;
         let context = handlebars.render_template(&context, &data)?;
-        Ok(context)
+        Ok(context
+            .split('\0')
+            .enumerate()
+            .map(|(i, x)| match i % 2 {
+                0 => x,
+                _ => x
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|n| verbatim.get(n))
+                    .map_or(x, |x| x.as_str()),
+            })
+            .collect())
+    }
+
+    /// Renders the stored `variables` into `text`, without escaping their values.
+    ///
+    /// Placeholders of other names are kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::RenderError`] if there is an issue during Handlebars rendering.
+    pub fn render_variables(&self, text: &str, variables: &JsonPlusObject) -> Result<String> {
+        if variables.properties.is_empty() || !text.contains("{{") {
+            return Ok(text.to_string());
+        }
+        let mut handlebars = Self::handlebars(None);
+        handlebars.register_escape_fn(handlebars::no_escape);
+        let variables: serde_json::Value = variables.into();
+        Ok(handlebars.render_template(text, &variables)?)
+    }
+
+    /// Renders the stored `variables` into the string values of `data`.
+    ///
+    /// This lets the `data` of `@include` and `@inline` refer to stored variables.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::RenderError`] if there is an issue during Handlebars rendering.
+    pub fn render_data(
+        &self,
+        data: &JsonPlusObject,
+        variables: &JsonPlusObject,
+    ) -> Result<JsonPlusObject> {
+        let mut rendered = JsonPlusObject::new();
+        for (key, value) in &data.properties {
+            let value = match value {
+                JsonPlusEntity::SingleQuotedString(x) => {
+                    JsonPlusEntity::SingleQuotedString(self.render_variables(x, variables)?)
+                }
+                JsonPlusEntity::DoubleQuotedString(x) => {
+                    JsonPlusEntity::DoubleQuotedString(self.render_variables(x, variables)?)
+                }
+                JsonPlusEntity::NudeString(x) => {
+                    JsonPlusEntity::NudeString(self.render_variables(x, variables)?)
+                }
+                JsonPlusEntity::Object(x) => {
+                    JsonPlusEntity::Object(self.render_data(x, variables)?)
+                }
+                x => x.clone(),
+            };
+            rendered.insert(key.clone(), value);
+        }
+        Ok(rendered)
     }
 
     pub fn process_context_with_data_from_parameters(
@@ -1563,11 +2235,35 @@ impl Worker {
         jpe: &JsonPlusEntity,
         range: &Range,
     ) -> Result<ModelContent> {
+        self.context_from_jpe(jpe, range, false)
+    }
+
+    /// Collects the contexts referenced by `jpe` like [`Worker::process_context_from_jpe`],
+    /// as they are, without executing them.
+    pub fn collect_context_from_jpe(
+        &self,
+        jpe: &JsonPlusEntity,
+        range: &Range,
+    ) -> Result<ModelContent> {
+        self.context_from_jpe(jpe, range, true)
+    }
+
+    /// Executes, or only collects if `collect_only` is set, the contexts referenced by `jpe`.
+    fn context_from_jpe(
+        &self,
+        jpe: &JsonPlusEntity,
+        range: &Range,
+        collect_only: bool,
+    ) -> Result<ModelContent> {
+        let run = |file_name: &str, data: Option<&JsonPlusObject>| match collect_only {
+            true => self.collect_as_is(file_name, data),
+            false => self.execute(file_name, data),
+        };
         match jpe {
             JsonPlusEntity::NudeString(file_name)
             | JsonPlusEntity::SingleQuotedString(file_name)
             | JsonPlusEntity::DoubleQuotedString(file_name) => {
-                let output = self.execute(&file_name, None)?;
+                let output = run(&file_name, None)?;
                 Ok(output)
             }
             JsonPlusEntity::Object(jpo) => {
@@ -1577,13 +2273,13 @@ impl Worker {
                     }
                 })?;
                 let data = jpo.get_as_object("data");
-                let output = self.execute(&file_name, data)?;
+                let output = run(&file_name, data)?;
                 Ok(output)
             }
             JsonPlusEntity::Array(jpa) => {
                 let mut output = ModelContent::new();
                 for item in jpa {
-                    output.extend(self.process_context_from_jpe(&item, range)?);
+                    output.extend(self.context_from_jpe(&item, range, collect_only)?);
                 }
                 Ok(output)
             }
@@ -1600,7 +2296,11 @@ impl Worker {
     pub fn start_task(
         &self,
         id: &Uuid,
-        task: impl FnOnce(mpsc::Sender<String>) -> std::result::Result<String, String> + Send + 'static,
+        task: impl FnOnce(
+                mpsc::Sender<String>,
+            ) -> std::result::Result<ProviderResponse, Vec<ProviderAttempt>>
+            + Send
+            + 'static,
     ) {
         self.task_manager
             .start_task(id.clone(), move |sender| task(sender));
@@ -1610,7 +2310,7 @@ impl Worker {
         self.task_manager.wait_output(id).map(|x| x.join("\n"))
     }
 
-    pub fn task_status(&self, id: &Uuid) -> TaskStatus<String, String> {
+    pub fn task_status(&self, id: &Uuid) -> TaskStatus<ProviderResponse, Vec<ProviderAttempt>> {
         self.task_manager.task_status(id)
     }
 }
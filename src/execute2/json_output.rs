@@ -0,0 +1,144 @@
+//! Structured JSON output of `@answer` tags.
+//!
+//! With `format: "json"` (or a `schema`), the model is asked to reply with a single JSON
+//! value. The value is extracted from the raw reply, tolerating surrounding text and
+//! markdown code fences, and validated against the JSON Schema given by the `schema`
+//! parameter, either inline or as the path of a schema file.
+use regex::Regex;
+use serde_json::{json, Value};
+
+use super::error::ExecuteError;
+use super::execute::Worker;
+use super::Result;
+use crate::ast2::{JsonPlusEntity, Parameters};
+
+use handlebars::Handlebars;
+
+/// Number of times a reply that is not valid JSON is asked again, by default.
+pub const DEFAULT_FORMAT_RETRIES: usize = 2;
+
+/// JSON output requested by the parameters of an `@answer` tag.
+pub struct JsonOutput {
+    /// The schema the reply must be valid against, if any.
+    schema: Option<Value>,
+    /// How many times an invalid reply is asked again.
+    pub retries: usize,
+}
+
+impl JsonOutput {
+    /// Reads the `format`, `schema` and `format_retries` parameters.
+    ///
+    /// Returns `None` if the tag does not request JSON output.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::UnsupportedParameterValue`] if a parameter has an invalid
+    /// value, or if the schema cannot be read or is not a valid JSON Schema.
+    pub fn from_parameters(worker: &Worker, parameters: &Parameters) -> Result<Option<Self>> {
+        match parameters.get("format") {
+            None => {}
+            Some(x) if x.as_string_only().as_deref() == Some("json") => {}
+            Some(x) => {
+                return Err(ExecuteError::UnsupportedParameterValue(format!(
+                    "format: {:?}",
+                    x
+                )))
+            }
+        }
+        let schema = match parameters.get("schema") {
+            None => None,
+            Some(JsonPlusEntity::Object(x)) => Some(Value::from(x)),
+            Some(x) => {
+                let path = x.as_string_only().ok_or_else(|| {
+                    ExecuteError::UnsupportedParameterValue(format!("schema: {:?}", x))
+                })?;
+                let content = worker.read_input_file(&path)?;
+                Some(serde_json::from_str(&content).map_err(|e| {
+                    ExecuteError::UnsupportedParameterValue(format!("schema: {}: {}", path, e))
+                })?)
+            }
+        };
+        if schema.is_none() && parameters.get("format").is_none() {
+            return Ok(None);
+        }
+        if let Some(schema) = &schema {
+            jsonschema::JSONSchema::compile(schema)
+                .map_err(|e| ExecuteError::UnsupportedParameterValue(format!("schema: {}", e)))?;
+        }
+        let retries = match parameters.get("format_retries") {
+            None => DEFAULT_FORMAT_RETRIES,
+            Some(x) => x
+                .as_integer_only()
+                .and_then(|x| usize::try_from(x).ok())
+                .ok_or_else(|| {
+                    ExecuteError::UnsupportedParameterValue(format!("format_retries: {:?}", x))
+                })?,
+        };
+        Ok(Some(JsonOutput { schema, retries }))
+    }
+
+    /// Returns the instructions appended to the prompt.
+    ///
+    /// `error` is why the previous reply was rejected, if it was.
+    pub fn instructions(&self, error: Option<&str>) -> Result<String> {
+        let schema = match &self.schema {
+            Some(x) => Some(serde_json::to_string_pretty(x)?),
+            None => None,
+        };
+        Ok(Handlebars::new().render_template(
+            super::JSON_OUTPUT_TEMPLATE,
+            &json!({ "schema": schema, "error": error }),
+        )?)
+    }
+
+    /// Extracts the JSON value from `reply` and validates it against the schema.
+    ///
+    /// Returns the value, or why the reply is rejected.
+    pub fn parse(&self, reply: &str) -> std::result::Result<Value, String> {
+        let value = extract_json(reply).ok_or("the reply contains no JSON value".to_string())?;
+        if let Some(schema) = &self.schema {
+            let schema = jsonschema::JSONSchema::compile(schema).map_err(|e| e.to_string())?;
+            let errors = match schema.validate(&value) {
+                Ok(()) => Vec::new(),
+                Err(errors) => errors
+                    .map(|e| match e.instance_path.to_string() {
+                        path if path.is_empty() => e.to_string(),
+                        path => format!("{}: {}", path, e),
+                    })
+                    .collect::<Vec<String>>(),
+            };
+            if !errors.is_empty() {
+                return Err(format!(
+                    "the JSON value does not match the schema: {}",
+                    errors.join("; ")
+                ));
+            }
+        }
+        Ok(value)
+    }
+}
+
+/// Extracts a JSON value from a model reply.
+///
+/// The whole reply is tried first, then the content of each markdown code fence, then
+/// the text between the first `{` (or `[`) and the last `}` (or `]`).
+pub fn extract_json(reply: &str) -> Option<Value> {
+    if let Ok(value) = serde_json::from_str(reply.trim()) {
+        return Some(value);
+    }
+    let fence = Regex::new(r"(?s)```[A-Za-z]*[ \t]*\n(.*?)```").expect("valid regex");
+    for captures in fence.captures_iter(reply) {
+        if let Ok(value) = serde_json::from_str(captures[1].trim()) {
+            return Some(value);
+        }
+    }
+    let mut candidates = [('{', '}'), ('[', ']')]
+        .iter()
+        .filter_map(|(open, close)| Some((reply.find(*open)?, reply.rfind(*close)?)))
+        .filter(|(begin, end)| begin < end)
+        .collect::<Vec<(usize, usize)>>();
+    candidates.sort();
+    candidates
+        .into_iter()
+        .find_map(|(begin, end)| serde_json::from_str(&reply[begin..=end]).ok())
+}
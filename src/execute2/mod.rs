@@ -30,9 +30,9 @@
 //!
 //! - **`tag_*.rs` (e.g., `tag_answer.rs`, `tag_include.rs`)**: Implementations of specific
 //!   tag behaviors. These are categorized into:
-//!     - **Static Tags** (`@include`, `@set`, `@forget`, `@comment`): Processed in a single pass,
+//!     - **Static Tags** (`@include`, `@file`, `@set`, `@forget`, `@if`, `@comment`): Processed in a single pass,
 //!       directly modifying the `Collector`'s state or content. `@comment` tags are ignored.
-//!     - **Dynamic Tags** (`@answer`, `@repeat`, `@inline`): Involve a state machine and can trigger
+//!     - **Dynamic Tags** (`@answer`, `@repeat`, `@inline`, `@foreach`, `@shell`, `@derive`, `@compact`): Involve a state machine and can trigger
 //!       multiple execution passes. They transform into anchors (`<!-- @@...@@ -->`)
 //!       and manage their state (e.g., `JustCreated`, `NeedProcessing`, `NeedInjection`, `Completed`)
 //!       persisted in external JSON files. These tags can involve calling external models
@@ -65,24 +65,34 @@ pub mod analyze;
 mod content;
 mod error;
 mod execute;
+mod json_output;
 mod names;
 mod tag_answer;
+mod tag_compact;
+mod tag_derive;
 mod tag_done;
+mod tag_file;
+mod tag_foreach;
 mod tag_forget;
+mod tag_if;
 mod tag_include;
 mod tag_inline;
 mod tag_repeat;
 mod tag_set;
+mod tag_shell;
 mod tag_task;
 mod tags;
 mod utils;
 
 pub use self::error::{ExecuteError, Result};
-pub use content::{ModelContent, ModelContentItem};
+pub use content::{
+    ChatMessage, ChatRole, ModelContent, ModelContentItem, PromptConfig, PromptFormat,
+};
 
-pub use analyze::{analyze_context, AnchorAnalysis, AnchorState, ContextAnalysis};
+pub use analyze::{analyze_context, collect_usage, AnchorAnalysis, AnchorState, ContextAnalysis};
 pub use execute::collect_context;
 pub use execute::execute_context;
+pub use execute::{execute_context_with_options, ExecuteOptions};
 
 const TASK_ANCHOR_PLACEHOLDER: &str =
     "Execute only the next instruction exactly as given — treat it as a single isolated step; do not infer, continue, or perform any other action beyond it.";
@@ -96,6 +106,8 @@ const TASK_ANCHOR_PLACEHOLDER: &str =
 //"#;
 const NO_CHOICE_MESSAGE: &str = "[No choice was taken - {{reply}}].";
 const MANY_CHOICES_MESSAGE: &str = "[Many choices were taken - {{reply}}].";
+const FEW_CHOICES_MESSAGE: &str = "[Too few choices were taken - {{reply}}].";
+const CHOICE_WITH_REASONING_TEMPLATE: &str = "{{{value}}}\n\n{{{reasoning}}}";
 
 const CHOICE_TEMPLATE: &str = r#"
 
@@ -114,3 +126,102 @@ Rules:
 - Do NOT use § anywhere except in the CHOICE line
 - Keep reasoning clear and concise
 "#;
+
+const CHOICE_MANY_TEMPLATE: &str = r#"
+
+INSTRUCTIONS:
+You must choose {{count}} in {{{choices}}}.
+First, reason on the choices.
+
+Then, provide your answer in this EXACT format:
+
+CHOICES: [YOUR_CHOICES]
+REASONING: [Your explanation]
+
+Rules:
+- YOUR_CHOICES must be a comma-separated list of tags in {{{choice_tags}}} to represent choices {{{choices}}}
+- Use the § symbol (required for parsing)
+- Do NOT use § anywhere except in the CHOICES line
+- Keep reasoning clear and concise
+"#;
+
+const JSON_OUTPUT_TEMPLATE: &str = r#"
+
+INSTRUCTIONS:
+Reply with a single JSON value and nothing else.
+{{#if schema}}
+The JSON value MUST be valid against this JSON Schema:
+{{{schema}}}
+{{/if}}
+{{#if error}}
+Your previous reply was rejected because {{{error}}}.
+{{/if}}
+"#;
+
+#[cfg(test)]
+#[path = "tests/utils.rs"]
+mod test_utils;
+
+#[cfg(test)]
+#[path = "tests/test_execute_mock.rs"]
+mod test_execute_mock;
+
+#[cfg(test)]
+#[path = "tests/test_execute_cassette.rs"]
+mod test_execute_cassette;
+
+#[cfg(test)]
+#[path = "tests/test_execute_cache.rs"]
+mod test_execute_cache;
+
+#[cfg(test)]
+#[path = "tests/test_execute_usage.rs"]
+mod test_execute_usage;
+
+#[cfg(test)]
+#[path = "tests/test_execute_budget.rs"]
+mod test_execute_budget;
+
+#[cfg(test)]
+#[path = "tests/test_execute_json.rs"]
+mod test_execute_json;
+
+#[cfg(test)]
+#[path = "tests/test_execute_choice.rs"]
+mod test_execute_choice;
+
+#[cfg(test)]
+#[path = "tests/test_execute_store.rs"]
+mod test_execute_store;
+
+#[cfg(test)]
+#[path = "tests/test_execute_if.rs"]
+mod test_execute_if;
+
+#[cfg(test)]
+#[path = "tests/test_execute_foreach.rs"]
+mod test_execute_foreach;
+
+#[cfg(test)]
+#[path = "tests/test_execute_shell.rs"]
+mod test_execute_shell;
+
+#[cfg(test)]
+#[path = "tests/test_execute_file.rs"]
+mod test_execute_file;
+
+#[cfg(test)]
+#[path = "tests/test_execute_derive.rs"]
+mod test_execute_derive;
+
+#[cfg(test)]
+#[path = "tests/test_execute_compact.rs"]
+mod test_execute_compact;
+
+#[cfg(test)]
+#[path = "tests/test_execute_truncate.rs"]
+mod test_execute_truncate;
+
+#[cfg(test)]
+#[path = "tests/test_execute_prompt_format.rs"]
+mod test_execute_prompt_format;
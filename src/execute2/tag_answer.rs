@@ -7,17 +7,28 @@ use super::Result;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use super::content::{ModelContent, ModelContentItem};
+use super::content::{ModelContent, ModelContentItem, TruncationStrategy};
 use super::error::ExecuteError;
 use super::execute::{Collector, Worker};
+use super::json_output::JsonOutput;
 use super::tags::{
     Container, DynamicPolicy, DynamicPolicyMonoInput, DynamicPolicyMonoResult, DynamicState,
 };
-use crate::ast2::{JsonPlusEntity, Parameters, Range};
+use crate::agent::cache::{CacheEntry, CachedProvider, ResponseCache};
+use crate::agent::cassette::{CassetteMode, CassetteProvider};
+use crate::agent::provider::{
+    call_chain, Provider, ProviderAttempt, ProviderMetadata, RetryPolicy, ANCHOR_UUID_ENV,
+    CONTEXT_ENV,
+};
+use crate::agent::usage::{Pricing, TokenUsage};
+use crate::ast2::{Anchor, JsonPlusEntity, Parameters, Range};
 use crate::utils::task::TaskStatus;
 use std::str::FromStr;
+use std::time::Duration;
+use uuid::Uuid;
 
 use handlebars::Handlebars;
+use regex::Regex;
 
 /// Represents the current status of an `@answer` tag's execution.
 ///
@@ -43,6 +54,9 @@ pub enum AnswerStatus {
     Edited,
     /// The `@answer` processing has been interrupted because of some error.
     Interrupted,
+    /// The model call has not been started because a budget is exhausted; a later run
+    /// starts it if its budget allows.
+    Pending,
 }
 
 impl ToString for AnswerStatus {
@@ -56,6 +70,7 @@ impl ToString for AnswerStatus {
             AnswerStatus::Completed => "completed".to_string(),
             AnswerStatus::Edited => "edited".to_string(),
             AnswerStatus::Interrupted => "interrupted".to_string(),
+            AnswerStatus::Pending => "pending".to_string(),
         }
     }
 }
@@ -73,6 +88,7 @@ impl FromStr for AnswerStatus {
             "completed" => Ok(AnswerStatus::Completed),
             "edited" => Ok(AnswerStatus::Edited),
             "interrupted" => Ok(AnswerStatus::Interrupted),
+            "pending" => Ok(AnswerStatus::Pending),
             _ => Err(ExecuteError::UnsupportedStatus(s.to_string())),
         }
     }
@@ -96,6 +112,30 @@ pub struct AnswerState {
     pub context_hash: String,
     /// The reply hash
     pub reply_hash: String,
+    /// Information about the provider that produced the reply
+    #[serde(default)]
+    pub metadata: ProviderMetadata,
+    /// The failed provider attempts of the latest query
+    #[serde(default)]
+    pub failed_attempts: Vec<ProviderAttempt>,
+    /// The estimated tokens (and cost) of the latest query and reply
+    #[serde(default)]
+    pub usage: TokenUsage,
+    /// Why the query has not been started, when pending
+    #[serde(default)]
+    pub pending_reason: String,
+    /// The JSON value of the reply, when JSON output is requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json: Option<serde_json::Value>,
+    /// Why the replies to the latest query were rejected as JSON output, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub format_errors: Vec<String>,
+    /// The choices picked by the model, when choices are offered
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<String>,
+    /// The reasoning the model gave for its choices, when choices are offered
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub reasoning: String,
 }
 
 /// Implements the dynamic policy for the `@answer` tag.
@@ -148,6 +188,10 @@ impl DynamicPolicy for AnswerPolicy {
         );
         let (mut result, mut residual) =
             DynamicPolicyMonoResult::<Self::State>::from_inputs(inputs);
+        let parameters = residual
+            .worker
+            .integrate_provider_profile(residual.parameters)?;
+        residual.parameters = &parameters;
 
         let agent_hash = residual.parameters.get_as_string_only("prefix").map(|x| {
             Collector::normalized_hash(&format!(
@@ -162,6 +206,17 @@ impl DynamicPolicy for AnswerPolicy {
         });
         result.collector = result.collector.set_latest_agent_hash(agent_hash.clone());
 
+        if let (Container::EndAnchor(a0, a1), Some(name)) = (
+            &residual.container,
+            residual.parameters.get_as_string_only("store"),
+        ) {
+            if let Some(value) =
+                Self::stored_value(residual.worker, residual.document, a0, a1, &residual.state)?
+            {
+                result.collector = result.collector.set_variable(&name, value);
+            }
+        }
+
         match (residual.container, &residual.state.status) {
             (Container::Tag(_) | Container::BeginAnchor(_, _), &AnswerStatus::JustCreated) => {
                 // Prepare the query
@@ -171,7 +226,10 @@ impl DynamicPolicy for AnswerPolicy {
                 result.new_state = Some(residual.state);
                 result.do_next_pass = true;
             }
-            (Container::BeginAnchor(a0, _), &AnswerStatus::NeedProcessing) => {
+            (
+                Container::BeginAnchor(a0, _),
+                &AnswerStatus::NeedProcessing | &AnswerStatus::Pending,
+            ) => {
                 // Execute the model query
                 let prompt = residual
                     .worker
@@ -184,42 +242,101 @@ impl DynamicPolicy for AnswerPolicy {
                     prompt,
                     residual.parameters,
                 )?;
-                let prompt =
-                    residual
-                        .worker
-                        .craft_prompt(agent_hash, residual.parameters, &prompt)?;
-
-                residual.state.query = prompt.clone();
-                residual.state.raw_reply = String::new();
-                residual.state.reply = String::new();
+                let prompt = Self::postfix_content_with_json_output(
+                    residual.worker,
+                    prompt,
+                    residual.parameters,
+                    residual.state.format_errors.last(),
+                )?;
+                let prompt = Self::truncate_content_from_parameters(
+                    residual.worker,
+                    prompt,
+                    residual.parameters,
+                    &a0.uuid,
+                )?;
+                let timeout = Self::duration_from_parameters(residual.parameters, "timeout")?;
+                let retry_policy = Self::retry_policy_from_parameters(residual.parameters)?;
+                let mut request = residual.worker.craft_provider_request(
+                    agent_hash,
+                    residual.parameters,
+                    prompt,
+                    timeout,
+                )?;
+                request
+                    .environment
+                    .insert(ANCHOR_UUID_ENV.to_string(), a0.uuid.to_string());
+                if let Some(context) = result.collector.current_context() {
+                    request
+                        .environment
+                        .insert(CONTEXT_ENV.to_string(), context.display().to_string());
+                }
+                let query = request.prompt();
 
-                let provider = match residual.parameters.get("provider") {
-                    Some(
-                        JsonPlusEntity::NudeString(x)
-                        | JsonPlusEntity::SingleQuotedString(x)
-                        | JsonPlusEntity::DoubleQuotedString(x),
-                    ) => x,
-                    Some(x) => {
-                        return Err(ExecuteError::UnsupportedParameterValue(format!(
-                            "bad provider: {:?}",
-                            x
-                        )));
+                let local_reply: Option<Box<dyn Provider>> = match residual.worker.cassette() {
+                    Some(cassette) if cassette.mode() == CassetteMode::Replay => {
+                        // Serve the reply from the cassette, never call the provider
+                        let query_hash = Collector::normalized_hash(&query);
+                        let reply = cassette
+                            .lookup(&query_hash)
+                            .ok_or(ExecuteError::CassetteMiss(query_hash))?;
+                        Some(Box::new(CassetteProvider { reply }))
                     }
+                    // Identical query already answered by the same provider
+                    _ => Self::response_cache(residual.worker, residual.parameters, &query)
+                        .and_then(|(cache, key)| cache.lookup(&key))
+                        .map(|entry| Box::new(CachedProvider { entry }) as Box<dyn Provider>),
+                };
+                let providers: Vec<Box<dyn Provider>> = match local_reply {
+                    Some(provider) => vec![provider],
                     None => {
-                        return Err(ExecuteError::MissingParameter("provider".to_string()));
+                        if let Some(reason) =
+                            Self::budget_exceeded(residual.worker, residual.parameters, &query)?
+                        {
+                            // Leave the answer pending, without failing the run
+                            if residual.worker.usage_tally().mark_pending(&a0.uuid) {
+                                tracing::warn!("Answer {} left pending: {}", a0.uuid, reason);
+                            }
+                            if residual.state.status != AnswerStatus::Pending
+                                || residual.state.pending_reason != reason
+                            {
+                                residual.state.status = AnswerStatus::Pending;
+                                residual.state.pending_reason = reason;
+                                result.new_state = Some(residual.state);
+                                result.do_next_pass = true;
+                            }
+                            return Ok(result);
+                        }
+                        residual.worker.usage_tally().start_call();
+                        match residual.parameters.get("provider") {
+                            Some(x) => {
+                                residual.worker.providers().resolve_chain(x).map_err(|e| {
+                                    ExecuteError::UnsupportedParameterValue(format!(
+                                        "provider: {}",
+                                        e
+                                    ))
+                                })?
+                            }
+                            None => {
+                                return Err(ExecuteError::MissingParameter("provider".to_string()));
+                            }
+                        }
                     }
-                }
-                .clone();
+                };
+
+                residual.state.query = query;
+                residual.state.raw_reply = String::new();
+                residual.state.reply = String::new();
+                residual.state.failed_attempts = Vec::new();
+                residual.state.pending_reason = String::new();
 
                 residual.worker.start_task(&a0.uuid, move |sender| {
-                    let progress_callback = move |chunk: &str| {
-                        // Send each chunk through the sender
-                        let _ = sender.send(chunk.to_string());
-                    };
-                    let response =
-                        crate::agent::shell::shell_call(&provider, &prompt, progress_callback)
-                            .map_err(|e| ExecuteError::ShellError(e.to_string()));
-                    response.map_err(|x| x.to_string())
+                    call_chain(&providers, &request, &retry_policy, || {
+                        let sender = sender.clone();
+                        Box::new(move |chunk: &str| {
+                            // Send each chunk through the sender
+                            let _ = sender.send(chunk.to_string());
+                        })
+                    })
                 });
 
                 residual.state.status = AnswerStatus::Processing;
@@ -259,9 +376,80 @@ impl DynamicPolicy for AnswerPolicy {
                             result.do_next_pass = true;
                         }
                         (TaskStatus::Done(response), _) => {
+                            residual.state.metadata = response.metadata;
+                            residual.state.failed_attempts = response.failed_attempts;
+                            let mut response = response.text;
+                            // Keep the end anchor on its own line
+                            if !response.is_empty() && !response.ends_with('\n') {
+                                response.push('\n');
+                            }
                             residual.state.raw_reply = response.clone();
-                            let response =
-                                Self::process_response_with_choice(response, residual.parameters)?;
+                            let pricing = Self::pricing_from_parameters(residual.parameters)?;
+                            residual.state.usage = TokenUsage::estimate(
+                                residual.worker.tokenizer(),
+                                &residual.state.query,
+                                &residual.state.raw_reply,
+                                pricing.as_ref(),
+                            );
+                            residual
+                                .worker
+                                .usage_tally()
+                                .record(&residual.state.metadata.name, &residual.state.usage);
+                            residual.worker.record_in_cassette(
+                                &residual.state.query,
+                                &residual.state.raw_reply,
+                            )?;
+                            let response = match JsonOutput::from_parameters(
+                                residual.worker,
+                                residual.parameters,
+                            )? {
+                                Some(json_output) => match json_output.parse(&response) {
+                                    Ok(value) => {
+                                        let response =
+                                            format!("{}\n", serde_json::to_string_pretty(&value)?);
+                                        residual.state.json = Some(value);
+                                        response
+                                    }
+                                    Err(error) => {
+                                        residual.state.format_errors.push(error);
+                                        if residual.state.format_errors.len() > json_output.retries
+                                        {
+                                            tracing::warn!(
+                                                "Answer {} rejected as JSON output: {:?}",
+                                                a0.uuid,
+                                                residual.state.format_errors
+                                            );
+                                            residual.state.status = AnswerStatus::Interrupted;
+                                        } else {
+                                            // Ask again, telling the model what was wrong
+                                            residual.state.status = AnswerStatus::NeedProcessing;
+                                        }
+                                        result.new_state = Some(residual.state);
+                                        result.do_next_pass = true;
+                                        return Ok(result);
+                                    }
+                                },
+                                None => response,
+                            };
+                            if let Some((cache, key)) = Self::response_cache(
+                                residual.worker,
+                                residual.parameters,
+                                &residual.state.query,
+                            ) {
+                                let entry = CacheEntry {
+                                    reply: residual.state.raw_reply.clone(),
+                                    metadata: residual.state.metadata.clone(),
+                                };
+                                cache
+                                    .store(&key, &entry)
+                                    .map_err(|e| ExecuteError::Generic(e.to_string()))?;
+                            }
+                            let response = Self::process_response_with_choice(
+                                residual.worker,
+                                response,
+                                residual.parameters,
+                                &mut residual.state,
+                            )?;
                             residual.state.reply_hash = Collector::normalized_hash(&response);
                             residual.state.reply = response;
                             residual.state.status = AnswerStatus::NeedInjection;
@@ -269,8 +457,9 @@ impl DynamicPolicy for AnswerPolicy {
                             result.new_state = Some(residual.state);
                             result.do_next_pass = true;
                         }
-                        (TaskStatus::Error(error), _) => {
-                            tracing::warn!("Task {} got error {:?}!", a0.uuid, error);
+                        (TaskStatus::Error(failed_attempts), _) => {
+                            tracing::warn!("Task {} got error {:?}!", a0.uuid, failed_attempts);
+                            residual.state.failed_attempts = failed_attempts;
                             residual.state.status = AnswerStatus::Interrupted;
                             result.new_state = Some(residual.state);
                             result.do_next_pass = true;
@@ -351,6 +540,7 @@ impl DynamicPolicy for AnswerPolicy {
                 if !residual.readonly {
                     residual.state.status = AnswerStatus::NeedProcessing; // Modified
                     residual.state.reply = String::new(); // Modified
+                    residual.state.format_errors = Vec::new();
                     result.new_state = Some(residual.state);
                     result.new_output = Some(String::new());
                 }
@@ -369,32 +559,279 @@ impl DynamicPolicy for AnswerPolicy {
 }
 
 impl AnswerPolicy {
-    /// Appends a choice-related postfix to the `ModelContent` if a `choose` parameter is present.
+    /// Executes a single step as [`AnswerPolicy::mono`], except that a `Completed` answer
+    /// whose input changed is repeated if `is_dynamic`, whatever the `dynamic` parameter.
     ///
-    /// This method is used when the `@answer` tag is configured to present a set of choices
-    /// to the model. It formats these choices into a system message and appends it to the
-    /// current prompt.
+    /// This lets the tags built on `@answer` be dynamic by default.
+    pub(crate) fn mono_with_dynamic(
+        inputs: DynamicPolicyMonoInput<AnswerState>,
+        is_dynamic: bool,
+    ) -> Result<DynamicPolicyMonoResult<AnswerState>> {
+        if let (Container::BeginAnchor(_, _), AnswerStatus::Completed) =
+            (&inputs.container, &inputs.state.status)
+        {
+            if is_dynamic && inputs.state.context_hash != inputs.input_hash {
+                // Input modified, repeat
+                let (mut result, mut residual) =
+                    DynamicPolicyMonoResult::<AnswerState>::from_inputs(inputs);
+                residual.state.status = AnswerStatus::Repeat;
+                result.new_state = Some(residual.state);
+                result.do_next_pass = true;
+                return Ok(result);
+            }
+        }
+        Self::mono(inputs)
+    }
+
+    /// Returns the value stored by the `store` parameter, if the answer is done.
     ///
-    /// # Arguments
+    /// This is the parsed JSON value with JSON output, or else the reply as it is in the
+    /// document (or in the `output` file), including any edit made by the user.
     ///
-    /// * `worker` - A reference to the [`Worker`] instance.
-    /// * `content` - The current [`ModelContent`] to which the postfix will be added.
-    /// * `parameters` - The [`Parameters`] of the `@answer` tag, potentially containing a `choose` parameter.
+    /// # Errors
     ///
-    /// # Returns
+    /// Returns [`ExecuteError`] if the `output` file cannot be read.
+    fn stored_value(
+        worker: &Worker,
+        document: &str,
+        begin: &Anchor,
+        end: &Anchor,
+        state: &AnswerState,
+    ) -> Result<Option<JsonPlusEntity>> {
+        match (&state.status, &state.json) {
+            (AnswerStatus::Completed, Some(json)) => Ok(Some(JsonPlusEntity::from(json))),
+            (AnswerStatus::Completed | AnswerStatus::Edited, _) => {
+                let content = match worker.is_output_redirected(&begin.parameters)? {
+                    Some(output_file) => worker.read_file(&output_file)?,
+                    None => Worker::get_range(
+                        document,
+                        &Range {
+                            begin: begin.range.end,
+                            end: end.range.begin,
+                        },
+                    )?
+                    .to_string(),
+                };
+                Ok(Some(JsonPlusEntity::DoubleQuotedString(
+                    content.trim().to_string(),
+                )))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Reads a duration in seconds (integer or decimal) from the `key` parameter.
     ///
-    /// A `Result` containing the updated `ModelContent` with the choice postfix.
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::UnsupportedParameterValue`] if the value is not a
+    /// non-negative number.
+    pub(crate) fn duration_from_parameters(
+        parameters: &Parameters,
+        key: &str,
+    ) -> Result<Option<Duration>> {
+        let seconds = match parameters.get(key) {
+            None => return Ok(None),
+            Some(JsonPlusEntity::Integer(x)) => *x as f64,
+            Some(JsonPlusEntity::Float(x)) => *x,
+            Some(x) => {
+                return Err(ExecuteError::UnsupportedParameterValue(format!(
+                    "{}: {:?}",
+                    key, x
+                )))
+            }
+        };
+        Duration::try_from_secs_f64(seconds)
+            .map(Some)
+            .map_err(|_| ExecuteError::UnsupportedParameterValue(format!("{}: {}", key, seconds)))
+    }
+
+    /// Removes content from `prompt` to fit the `max_context` parameter, if given.
+    ///
+    /// The limit is in tokens, as estimated by the tokenizer of the execution, given as
+    /// `max_context: <tokens>` or `max_context: { tokens: <tokens> }`, or in characters,
+    /// given as `max_context: { chars: <chars> }`. The `truncate` parameter picks the
+    /// [`TruncationStrategy`], `oldest` (the default) or `middle`.
     ///
     /// # Errors
     ///
-    /// Returns [`ExecuteError::UnsupportedParameterValue`] if the `choose` parameter
-    /// has an invalid format.
-    fn postfix_content_with_choice(
+    /// Returns [`ExecuteError::UnsupportedParameterValue`] if either parameter has an invalid value.
+    fn truncate_content_from_parameters(
         worker: &Worker,
-        content: ModelContent,
+        prompt: ModelContent,
         parameters: &Parameters,
+        uuid: &Uuid,
     ) -> Result<ModelContent> {
-        let choices = match parameters.get("choose") {
+        let max_context = match parameters.get("max_context") {
+            None => return Ok(prompt),
+            Some(x) => x,
+        };
+        let limit = |x: Option<&JsonPlusEntity>| {
+            x.and_then(|x| x.as_integer_only())
+                .and_then(|x| u64::try_from(x).ok())
+        };
+        let (max_size, in_tokens) = match max_context {
+            JsonPlusEntity::Object(x) => match (limit(x.get("tokens")), limit(x.get("chars"))) {
+                (Some(tokens), None) => (Some(tokens), true),
+                (None, Some(chars)) => (Some(chars), false),
+                _ => (None, true),
+            },
+            x => (limit(Some(x)), true),
+        };
+        let max_size = max_size.ok_or_else(|| {
+            ExecuteError::UnsupportedParameterValue(format!("max_context: {:?}", max_context))
+        })?;
+        let strategy = match parameters.get_as_string_only("truncate") {
+            None => TruncationStrategy::Oldest,
+            Some(x) => TruncationStrategy::from_str(&x)
+                .map_err(|e| ExecuteError::UnsupportedParameterValue(format!("truncate: {}", e)))?,
+        };
+        let unit = if in_tokens { "tokens" } else { "characters" };
+        let size = |text: &str| match in_tokens {
+            true => worker.tokenizer().count_tokens(text),
+            false => text.chars().count() as u64,
+        };
+        let (truncated, removed) = prompt.truncate(max_size, strategy, size);
+        if removed > 0 {
+            tracing::warn!(
+                "Answer {}: removed {} messages to fit max_context ({} {})",
+                uuid,
+                removed,
+                max_size,
+                unit
+            );
+        }
+        let total: u64 = truncated.0.iter().map(|x| size(&x.to_string())).sum();
+        if total > max_size {
+            tracing::warn!(
+                "Answer {}: prompt of {} {} still exceeds max_context ({} {})",
+                uuid,
+                total,
+                unit,
+                max_size,
+                unit
+            );
+        }
+        Ok(truncated)
+    }
+
+    /// Returns the response cache and the key of `query`, if the tag uses the cache.
+    ///
+    /// Caching is enabled for a whole execution, and can be turned off for a tag with
+    /// `cache: false`. The key covers the query and the `provider` parameter, so the same
+    /// query sent to a different provider is not served from the cache.
+    fn response_cache<'w>(
+        worker: &'w Worker,
+        parameters: &Parameters,
+        query: &str,
+    ) -> Option<(&'w ResponseCache, String)> {
+        let cache = worker.response_cache()?;
+        if !parameters.get_as_bool("cache").unwrap_or(true) {
+            return None;
+        }
+        let provider = parameters.get("provider")?.to_string();
+        let key = ResponseCache::key(&provider, &Collector::normalize_text(query));
+        Some((cache, key))
+    }
+
+    /// Returns why sending `query` to a provider would exceed the budget, if it would.
+    ///
+    /// The limits of the `max_tokens_per_run`, `max_calls_per_run` and `max_prompt_chars`
+    /// parameters take precedence over the budget of the execution.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::UnsupportedParameterValue`] if a limit is not a non-negative integer.
+    fn budget_exceeded(
+        worker: &Worker,
+        parameters: &Parameters,
+        query: &str,
+    ) -> Result<Option<String>> {
+        let limit = |key: &str, default: Option<u64>| -> Result<Option<u64>> {
+            match parameters.get(key) {
+                None => Ok(default),
+                Some(x) => x
+                    .as_integer_only()
+                    .and_then(|x| u64::try_from(x).ok())
+                    .map(Some)
+                    .ok_or_else(|| {
+                        ExecuteError::UnsupportedParameterValue(format!("{}: {:?}", key, x))
+                    }),
+            }
+        };
+        let budget = worker.budget();
+        let tally = worker.usage_tally();
+        if let Some(max) = limit("max_prompt_chars", budget.max_prompt_chars)? {
+            let chars = query.chars().count() as u64;
+            if chars > max {
+                return Ok(Some(format!(
+                    "prompt of {} characters exceeds max_prompt_chars ({})",
+                    chars, max
+                )));
+            }
+        }
+        if let Some(max) = limit("max_calls_per_run", budget.max_calls_per_run)? {
+            if tally.calls() >= max {
+                return Ok(Some(format!("max_calls_per_run ({}) reached", max)));
+            }
+        }
+        if let Some(max) = limit("max_tokens_per_run", budget.max_tokens_per_run)? {
+            let used = tally.summary().total.usage;
+            let tokens =
+                used.input_tokens + used.output_tokens + worker.tokenizer().count_tokens(query);
+            if tokens > max {
+                return Ok(Some(format!(
+                    "~{} tokens would exceed max_tokens_per_run ({})",
+                    tokens, max
+                )));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads the pricing of the provider from the `pricing` parameter, if given.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::UnsupportedParameterValue`] if the parameter has an invalid value.
+    fn pricing_from_parameters(parameters: &Parameters) -> Result<Option<Pricing>> {
+        parameters
+            .get("pricing")
+            .map(|x| {
+                Pricing::from_json_plus(x)
+                    .map_err(|e| ExecuteError::UnsupportedParameterValue(format!("{:#}", e)))
+            })
+            .transpose()
+    }
+
+    /// Reads the retry policy of provider calls from the `retries` and `retry_backoff` parameters.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::UnsupportedParameterValue`] if either parameter has an invalid value.
+    fn retry_policy_from_parameters(parameters: &Parameters) -> Result<RetryPolicy> {
+        let mut policy = RetryPolicy::default();
+        if let Some(retries) = parameters.get("retries") {
+            policy.retries = retries
+                .as_integer_only()
+                .and_then(|x| u32::try_from(x).ok())
+                .ok_or_else(|| {
+                    ExecuteError::UnsupportedParameterValue(format!("retries: {:?}", retries))
+                })?;
+        }
+        if let Some(retry_backoff) = Self::duration_from_parameters(parameters, "retry_backoff")? {
+            policy.retry_backoff = retry_backoff;
+        }
+        Ok(policy)
+    }
+
+    /// Reads the choices offered to the model from the `choose` parameter, if present.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::UnsupportedChoice`] if a choice of an array is not a scalar.
+    fn choices_from_parameters(parameters: &Parameters) -> Result<Option<Vec<String>>> {
+        match parameters.get("choose") {
             Some(JsonPlusEntity::Array(choices_list)) => {
                 let mut choices = Vec::new();
                 for choice in choices_list {
@@ -409,94 +846,261 @@ impl AnswerPolicy {
                         }
                     }
                 }
-                Some(choices)
+                Ok(Some(choices))
             }
-            Some(JsonPlusEntity::Object(x)) => {
-                let choices = x
-                    .properties
+            Some(JsonPlusEntity::Object(x)) => Ok(Some(
+                x.properties
                     .keys()
                     .map(|x| x.to_string())
-                    .collect::<Vec<String>>();
-                Some(choices)
+                    .collect::<Vec<String>>(),
+            )),
+            _ => Ok(None),
+        }
+    }
+
+    /// Reads how many of `choice_count` choices the model may pick with `choose_many: true`.
+    ///
+    /// Returns the minimum and maximum count, from the `min_choices` (default 1) and
+    /// `max_choices` (default all) parameters, or `None` if a single choice is expected.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::UnsupportedParameterValue`] if a count is not a non-negative
+    /// integer, or if the counts cannot be satisfied.
+    fn choice_range_from_parameters(
+        parameters: &Parameters,
+        choice_count: usize,
+    ) -> Result<Option<(usize, usize)>> {
+        if !parameters.get_as_bool("choose_many").unwrap_or(false) {
+            return Ok(None);
+        }
+        let count = |key: &str, default: usize| -> Result<usize> {
+            match parameters.get(key) {
+                None => Ok(default),
+                Some(x) => x
+                    .as_integer_only()
+                    .and_then(|x| usize::try_from(x).ok())
+                    .ok_or_else(|| {
+                        ExecuteError::UnsupportedParameterValue(format!("{}: {:?}", key, x))
+                    }),
             }
-            _ => None,
         };
-        match choices {
-            Some(ref x) => {
-                let choice_tags = x
-                    .iter()
-                    .map(|x| Self::choice_tag_from_choice(x))
-                    .collect::<Vec<String>>();
-                let handlebars = Handlebars::new();
-                let json_choices = match choices {
-                    Some(c) => serde_json::Value::Array(
-                        c.iter().cloned().map(serde_json::Value::String).collect(),
-                    ),
-                    None => {
-                        return Err(ExecuteError::MissingChoice {
-                            range: parameters.range,
-                        });
-                    }
-                };
-                let postfix = handlebars.render_template(
-                    super::CHOICE_TEMPLATE,
-                    &json!({ "choices": json_choices, "choice_tags": choice_tags }),
-                )?;
-                let postfix = ModelContentItem::merge_upstream(&postfix);
-                let postfix = ModelContent::from_item(postfix);
+        let min = count("min_choices", 1)?;
+        let max = count("max_choices", choice_count)?;
+        if max == 0 || min > max || min > choice_count {
+            return Err(ExecuteError::UnsupportedParameterValue(format!(
+                "min_choices: {}, max_choices: {} of {} choices",
+                min, max, choice_count
+            )));
+        }
+        Ok(Some((min, max)))
+    }
+
+    /// Describes how many choices the model must pick, for the prompt.
+    fn describe_choice_range(min: usize, max: usize, choice_count: usize) -> String {
+        match (min, max) {
+            (min, max) if min == max => format!("exactly {} options", min),
+            (0, max) if max == choice_count => "any number of options".to_string(),
+            (min, max) if max == choice_count => format!("at least {} options", min),
+            (0, max) => format!("at most {} options", max),
+            (min, max) => format!("between {} and {} options", min, max),
+        }
+    }
+
+    /// Appends a choice-related postfix to the `ModelContent` if a `choose` parameter is present.
+    ///
+    /// This method is used when the `@answer` tag is configured to present a set of choices
+    /// to the model. It formats these choices into a system message and appends it to the
+    /// current prompt. With `choose_many: true` the model is asked to pick several choices.
+    ///
+    /// # Arguments
+    ///
+    /// * `worker` - A reference to the [`Worker`] instance.
+    /// * `content` - The current [`ModelContent`] to which the postfix will be added.
+    /// * `parameters` - The [`Parameters`] of the `@answer` tag, potentially containing a `choose` parameter.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the updated `ModelContent` with the choice postfix.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::UnsupportedParameterValue`] if the `choose` parameter
+    /// has an invalid format.
+    fn postfix_content_with_choice(
+        worker: &Worker,
+        content: ModelContent,
+        parameters: &Parameters,
+    ) -> Result<ModelContent> {
+        let choices = match Self::choices_from_parameters(parameters)? {
+            Some(x) => x,
+            None => return Ok(content),
+        };
+        let choice_tags = choices
+            .iter()
+            .map(|x| Self::choice_tag_from_choice(x))
+            .collect::<Vec<String>>();
+        let handlebars = Handlebars::new();
+        let json_choices = serde_json::Value::Array(
+            choices
+                .iter()
+                .cloned()
+                .map(serde_json::Value::String)
+                .collect(),
+        );
+        let postfix = match Self::choice_range_from_parameters(parameters, choices.len())? {
+            None => handlebars.render_template(
+                super::CHOICE_TEMPLATE,
+                &json!({ "choices": json_choices, "choice_tags": choice_tags }),
+            )?,
+            Some((min, max)) => handlebars.render_template(
+                super::CHOICE_MANY_TEMPLATE,
+                &json!({
+                    "choices": json_choices,
+                    "choice_tags": choice_tags,
+                    "count": Self::describe_choice_range(min, max, choices.len()),
+                }),
+            )?,
+        };
+        let postfix = ModelContentItem::merge_upstream(&postfix);
+        let postfix = ModelContent::from_item(postfix);
+        Ok(worker.postfix_content(content, postfix))
+    }
+
+    /// Appends the JSON output instructions to the `ModelContent`, if JSON output is requested.
+    ///
+    /// When the previous reply was rejected, `error` tells the model why.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::UnsupportedParameterValue`] if the `format`, `schema` or
+    /// `format_retries` parameters have an invalid value.
+    fn postfix_content_with_json_output(
+        worker: &Worker,
+        content: ModelContent,
+        parameters: &Parameters,
+        error: Option<&String>,
+    ) -> Result<ModelContent> {
+        match JsonOutput::from_parameters(worker, parameters)? {
+            Some(json_output) => {
+                let postfix = json_output.instructions(error.map(|x| x.as_str()))?;
+                let postfix = ModelContent::from_item(ModelContentItem::merge_upstream(&postfix));
                 Ok(worker.postfix_content(content, postfix))
             }
             None => Ok(content),
         }
     }
-    /// Processes the model's response, extracting the chosen option if a `choose` parameter was used.
+
+    /// Extracts the reasoning the model gave for its choices from `response`.
     ///
-    /// If the `@answer` tag was configured with a `choose` parameter (object variant),
-    /// this method attempts to identify which choice tag is present in the model's `response`.
-    /// It then returns the corresponding value from the `choose` parameter.
+    /// This is the text following `REASONING:`, or else the whole response without the
+    /// lines holding choice tags.
+    fn reasoning_from_response(response: &str) -> String {
+        let reasoning = Regex::new(r"(?is)REASONING:(.*)").expect("valid regex");
+        match reasoning.captures(response) {
+            Some(x) => x[1].trim().to_string(),
+            None => response
+                .lines()
+                .filter(|x| !x.contains(&Self::choice_tag_from_choice("")))
+                .collect::<Vec<&str>>()
+                .join("\n")
+                .trim()
+                .to_string(),
+        }
+    }
+
+    /// Processes the model's response, extracting the chosen options if a `choose` parameter was used.
+    ///
+    /// The choices picked by the model and its reasoning are stored in `state`. If the
+    /// `@answer` tag was configured with a `choose` parameter (object variant), the
+    /// response is replaced by the value of the picked choice, or by the values of all the
+    /// picked choices, one per line, with `choose_many: true`. With `with_reasoning: true`
+    /// the reasoning follows the value(s). See [`Self::choice_value`] for branch values.
     ///
     /// # Arguments
     ///
+    /// * `worker` - A reference to the [`Worker`] instance, executing branch contexts.
     /// * `response` - The raw response string from the external model.
     /// * `parameters` - The [`Parameters`] of the `@answer` tag, potentially containing a `choose` parameter.
+    /// * `state` - The [`AnswerState`] receiving the picked choices and the reasoning.
     ///
     /// # Returns
     ///
     /// A `Result` containing the processed response string. This will be the chosen
-    /// value if applicable, or the original response if no `choose` parameter was used
-    /// or no choice was clearly indicated.
-    fn process_response_with_choice(response: String, parameters: &Parameters) -> Result<String> {
-        match parameters.get("choose") {
-            Some(JsonPlusEntity::Object(x)) => {
-                let choice_tags = x
-                    .properties
-                    .iter()
-                    .filter_map(|(key, value)| {
-                        match response.contains(&Self::choice_tag_from_choice(key)) {
-                            true => Some(value.to_prompt()),
-                            false => None,
-                        }
-                    })
-                    .collect::<Vec<String>>();
-                let handlebars = Handlebars::new();
-
-                let response = match choice_tags.len() {
-                    1 => choice_tags
-                        .get(0)
-                        .expect("There is one element!")
-                        .to_string(),
-                    0 => handlebars
-                        .render_template(super::NO_CHOICE_MESSAGE, &json!({ "reply": response }))?,
-                    _ => handlebars.render_template(
-                        super::MANY_CHOICES_MESSAGE,
-                        &json!({ "reply": response }),
+    /// value(s) if applicable, or the original response if no `choose` parameter was used
+    /// or the number of picked choices is not the expected one.
+    fn process_response_with_choice(
+        worker: &Worker,
+        response: String,
+        parameters: &Parameters,
+        state: &mut AnswerState,
+    ) -> Result<String> {
+        let choices = match Self::choices_from_parameters(parameters)? {
+            Some(x) => x,
+            None => return Ok(response),
+        };
+        state.choices = choices
+            .iter()
+            .filter(|x| response.contains(&Self::choice_tag_from_choice(x)))
+            .cloned()
+            .collect();
+        state.reasoning = Self::reasoning_from_response(&response);
+        let values = match parameters.get("choose") {
+            Some(JsonPlusEntity::Object(x)) => state
+                .choices
+                .iter()
+                .filter_map(|key| x.properties.get(key))
+                .map(|value| Self::choice_value(worker, value, parameters))
+                .collect::<Result<Vec<String>>>()?,
+            _ => return Ok(response),
+        };
+        let (min, max) =
+            Self::choice_range_from_parameters(parameters, choices.len())?.unwrap_or((1, 1));
+        let handlebars = Handlebars::new();
+
+        let response = match values.len() {
+            0 if min > 0 => handlebars
+                .render_template(super::NO_CHOICE_MESSAGE, &json!({ "reply": response }))?,
+            n if n < min => handlebars
+                .render_template(super::FEW_CHOICES_MESSAGE, &json!({ "reply": response }))?,
+            n if n > max => handlebars
+                .render_template(super::MANY_CHOICES_MESSAGE, &json!({ "reply": response }))?,
+            _ => {
+                let value = values.join("\n");
+                match parameters.get_as_bool("with_reasoning").unwrap_or(false) {
+                    true => handlebars.render_template(
+                        super::CHOICE_WITH_REASONING_TEMPLATE,
+                        &json!({ "value": value, "reasoning": state.reasoning }),
                     )?,
-                };
-                Ok(format!("{}\n", response))
+                    false => value,
+                }
             }
-            _ => Ok(response),
+        };
+        Ok(format!("{}\n", response))
+    }
+    /// Returns the text inserted for a picked choice whose value is `value`.
+    ///
+    /// A value `{ context: "<name>", data: { ... } }` is a branch: the context is executed,
+    /// with the given data, and its content is inserted. Any other value is inserted as is.
+    ///
+    /// # Errors
+    ///
+    /// Returns errors from the execution of the branch context.
+    fn choice_value(
+        worker: &Worker,
+        value: &JsonPlusEntity,
+        parameters: &Parameters,
+    ) -> Result<String> {
+        match value {
+            JsonPlusEntity::Object(x) if x.get("context").is_some() => Ok(worker
+                .process_context_from_jpe(value, &parameters.range)?
+                .to_string()
+                .trim_end()
+                .to_string()),
+            x => Ok(x.to_prompt()),
         }
     }
+
     /// Generates a unique tag string for a given choice.
     ///
     /// This tag is used internally to identify which choice the model has selected
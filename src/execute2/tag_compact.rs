@@ -0,0 +1,65 @@
+//! Implements the behavior for the dynamic `@compact` tag.
+//!
+//! The `@compact` tag replaces the content collected so far with a summary written by a
+//! model, so that long contexts, like diaries, fit the model window. It works like
+//! `@forget`, but the summary preserves the key information; being the content of the
+//! anchor, it can be edited by the user.
+//!
+//! The summary is asked as an `@answer` on the content collected so far, followed by an
+//! `instruction` (a default one asks for a summary). It is written again whenever the
+//! normalized hash of the content collected so far changes, unless `dynamic: false` is
+//! given.
+
+use super::content::ModelContentItem;
+use super::tag_answer::{AnswerPolicy, AnswerState, AnswerStatus};
+use super::tags::{Container, DynamicPolicy, DynamicPolicyMonoInput, DynamicPolicyMonoResult};
+use super::Result;
+
+/// The instruction asking for the summary, unless given by the `instruction` parameter.
+const DEFAULT_INSTRUCTION: &str = "Summarize all the conversation above, as it will be replaced by your summary. Keep the goals, the decisions taken, the facts established and the open questions; leave out everything else.";
+
+/// Implements the dynamic policy for the `@compact` tag.
+pub struct CompactPolicy;
+
+impl DynamicPolicy for CompactPolicy {
+    /// The state of a `@compact` anchor is the one of an `@answer` anchor.
+    type State = AnswerState;
+
+    /// Executes a single step of the `@compact` tag's lifecycle.
+    ///
+    /// The instruction is appended to the input, then the step is executed as for an
+    /// `@answer` tag. A `Completed` anchor whose input changed is repeated. When the
+    /// summary is in the anchor, the content collected so far is forgotten, so that the
+    /// summary replaces it.
+    fn mono(
+        mut inputs: DynamicPolicyMonoInput<Self::State>,
+    ) -> Result<DynamicPolicyMonoResult<Self::State>> {
+        tracing::debug!(
+            "tag_compact::CompactPolicy::mono\nState = {:?}",
+            inputs.state
+        );
+        let parameters = match inputs.container.clone() {
+            Container::Tag(tag) => &tag.parameters,
+            Container::BeginAnchor(anchor, _) | Container::EndAnchor(anchor, _) => {
+                &anchor.parameters
+            }
+        };
+        let instruction = parameters
+            .get_as_string_only("instruction")
+            .unwrap_or(DEFAULT_INSTRUCTION.to_string());
+        inputs.input.push(ModelContentItem::user(&instruction));
+
+        let is_begin = matches!(inputs.container, Container::BeginAnchor(_, _));
+        let status = inputs.state.status.clone();
+        let is_dynamic = parameters.get_as_bool("dynamic").unwrap_or(true);
+        let mut result = AnswerPolicy::mono_with_dynamic(inputs, is_dynamic)?;
+        let status = result
+            .new_state
+            .as_ref()
+            .map_or(status, |x| x.status.clone());
+        if is_begin && matches!(status, AnswerStatus::Completed | AnswerStatus::Edited) {
+            result.collector = result.collector.forget();
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,156 @@
+//! Implements the behavior for the dynamic `@derive` tag.
+//!
+//! The `@derive` tag transforms existing content through a model: it sends a source,
+//! followed by an `instruction`, to the provider, and injects the reply into its anchor,
+//! e.g. to keep a summary of a design document. The source is given by one of the
+//! following parameters:
+//! - `context: <name>`, the content collected from a context, as the `input` of `@answer`;
+//! - `anchor: <uuid>`, the content of an anchor of the same context;
+//! - `file: <path>`, the raw content of a project file.
+//!
+//! Unlike `@answer`, the prompt does not include the content collected so far, and the
+//! derived content is user content. The model call goes through the same steps as an
+//! `@answer`, with the same parameters and state; the anchor is derived again whenever
+//! the normalized hash of the source changes, unless `dynamic: false` is given. A source
+//! context is executed only to build the query; checking it for changes only collects it.
+
+use super::content::{ModelContent, ModelContentItem};
+use super::error::ExecuteError;
+use super::execute::{Collector, Worker};
+use super::tag_answer::{AnswerPolicy, AnswerState, AnswerStatus};
+use super::tags::{Container, DynamicPolicy, DynamicPolicyMonoInput, DynamicPolicyMonoResult};
+use super::Result;
+use crate::ast2::{AnchorKind, Content, Parameters, Range};
+
+/// Implements the dynamic policy for the `@derive` tag.
+pub struct DerivePolicy;
+
+impl DynamicPolicy for DerivePolicy {
+    /// The state of a `@derive` anchor is the one of an `@answer` anchor.
+    type State = AnswerState;
+
+    /// Executes a single step of the `@derive` tag's lifecycle.
+    ///
+    /// The input of the step is replaced by the source followed by the instruction, then
+    /// the step is executed as for an `@answer` tag. A `Completed` anchor whose source
+    /// changed is repeated.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::MissingDeriveSource`] if no source is given,
+    /// [`ExecuteError::MissingParameter`] if the `instruction` is missing, and
+    /// [`ExecuteError::DeriveAnchorNotFound`] if the source anchor does not exist.
+    fn mono(
+        mut inputs: DynamicPolicyMonoInput<Self::State>,
+    ) -> Result<DynamicPolicyMonoResult<Self::State>> {
+        tracing::debug!("tag_derive::DerivePolicy::mono\nState = {:?}", inputs.state);
+        let parameters = match inputs.container.clone() {
+            Container::Tag(tag) => &tag.parameters,
+            Container::BeginAnchor(anchor, _) | Container::EndAnchor(anchor, _) => {
+                &anchor.parameters
+            }
+        };
+        // The source is computed only by the steps using it, and a source context is
+        // executed only to build the query of a modifying pass, else just collected
+        let source = match (&inputs.container, &inputs.state.status) {
+            (
+                Container::BeginAnchor(_, _),
+                AnswerStatus::NeedProcessing | AnswerStatus::Pending,
+            ) => Some(Self::source(
+                inputs.worker,
+                inputs.document,
+                parameters,
+                inputs.readonly,
+            )?),
+            (Container::Tag(_), _) | (Container::BeginAnchor(_, _), AnswerStatus::Completed) => {
+                Some(Self::source(
+                    inputs.worker,
+                    inputs.document,
+                    parameters,
+                    true,
+                )?)
+            }
+            _ => None,
+        };
+        match source {
+            Some((input, input_hash)) => {
+                inputs.input = input;
+                inputs.input_hash = input_hash;
+            }
+            None => {
+                // Keep the hash of the latest query
+                inputs.input = ModelContent::new();
+                inputs.input_hash = inputs.state.context_hash.clone();
+            }
+        }
+
+        let is_dynamic = parameters.get_as_bool("dynamic").unwrap_or(true);
+        AnswerPolicy::mono_with_dynamic(inputs, is_dynamic)
+    }
+}
+
+impl DerivePolicy {
+    /// Returns the input of the model, the source followed by the instruction, and its
+    /// normalized hash.
+    ///
+    /// A source context is executed, or only collected if `collect_only` is set, e.g. to
+    /// check whether the source changed.
+    fn source(
+        worker: &Worker,
+        document: &str,
+        parameters: &Parameters,
+        collect_only: bool,
+    ) -> Result<(ModelContent, String)> {
+        let mut input = if let Some(context) = parameters.get("context") {
+            match collect_only {
+                true => worker.collect_context_from_jpe(context, &parameters.range)?,
+                false => worker.process_context_from_jpe(context, &parameters.range)?,
+            }
+        } else if let Some(uuid) = parameters.get_as_string_only("anchor") {
+            let content = Self::anchor_content(document, &uuid, parameters.range)?;
+            ModelContent::from_item(ModelContentItem::user(&content))
+        } else if let Some(file) = parameters.get_as_string_only("file") {
+            let content = worker.read_project_file(&file)?;
+            ModelContent::from_item(ModelContentItem::user(&content))
+        } else {
+            return Err(ExecuteError::MissingDeriveSource {
+                range: parameters.range,
+            });
+        };
+        let instruction = parameters
+            .get_as_string_only("instruction")
+            .ok_or_else(|| ExecuteError::MissingParameter("instruction".to_string()))?;
+        input.push(ModelContentItem::user(&instruction));
+        let input_hash = Collector::normalized_hash(&input.to_string());
+        Ok((input, input_hash))
+    }
+
+    /// Returns the content of the anchor of `document` with the given `uuid`.
+    fn anchor_content(document: &str, uuid: &str, range: Range) -> Result<String> {
+        let ast = crate::ast2::parse_document(document)?;
+        let find = |kind: AnchorKind| {
+            ast.content.iter().find_map(|x| match x {
+                Content::Anchor(anchor)
+                    if anchor.kind == kind && anchor.uuid.to_string() == uuid =>
+                {
+                    Some(anchor.range)
+                }
+                _ => None,
+            })
+        };
+        match (find(AnchorKind::Begin), find(AnchorKind::End)) {
+            (Some(begin), Some(end)) => Ok(Worker::get_range(
+                document,
+                &Range {
+                    begin: begin.end,
+                    end: end.begin,
+                },
+            )?
+            .to_string()),
+            _ => Err(ExecuteError::DeriveAnchorNotFound {
+                uuid: uuid.to_string(),
+                range,
+            }),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! This module implements the `FilePolicy` for the `@file` tag. The `@file` tag is
+//! a static tag that includes the raw content of project files, e.g. source files,
+//! into the current execution flow. Unlike `@include`, the files are read as they are:
+//! they are neither rendered as templates nor parsed for tags, so content with `{{` or
+//! `@` reaches the model untouched.
+//!
+//! The tag includes either a single file, named by its argument, or all the files
+//! matching a `glob` parameter. In the latter case each file is prefixed by its path
+//! and fenced, and files are skipped if they are excluded, ignored by git, not text or
+//! too large; the skipped files, but the excluded ones, are listed after the included
+//! ones, so that the model knows what is missing.
+use std::path::Path;
+
+use super::{ExecuteError, Result};
+
+use super::content::ModelContentItem;
+use super::execute::Worker;
+use super::tags::{Container, StaticPolicy, StaticPolicyMonoInput, StaticPolicyMonoResult};
+use crate::ast2::{JsonPlusEntity, Parameters};
+use crate::utils::git::git_ignored_files;
+
+/// Default for the `max_file_size` parameter, the size in bytes above which a file
+/// matching a glob is skipped.
+const DEFAULT_MAX_FILE_SIZE: u64 = 64 * 1024;
+/// Default for the `max_total_size` parameter, the size in bytes of all the files
+/// included by a glob above which further files are skipped.
+const DEFAULT_MAX_TOTAL_SIZE: u64 = 512 * 1024;
+
+/// Implements the static policy for the `@file` tag.
+///
+/// The `@file` tag reads files, named relative to the project root, and pushes
+/// their content into the current [`Collector`] as user content.
+pub struct FilePolicy;
+
+/// The reason why a file matching a glob is skipped.
+enum SkipReason {
+    /// The file is ignored by git.
+    Ignored,
+    /// The file is not valid UTF-8 text.
+    NotText,
+    /// The file is larger than `max_file_size`.
+    TooLarge(u64),
+    /// Including the file would exceed `max_total_size`.
+    TotalTooLarge,
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::Ignored => write!(f, "ignored by git"),
+            SkipReason::NotText => write!(f, "not a text file"),
+            SkipReason::TooLarge(size) => write!(f, "too large, {} bytes", size),
+            SkipReason::TotalTooLarge => write!(f, "total size limit reached"),
+        }
+    }
+}
+
+impl StaticPolicy for FilePolicy {
+    /// Collects the content of project files.
+    ///
+    /// Without a `glob` parameter, this method takes the name of the file from the tag's
+    /// arguments, reads it and pushes its content into the [`Collector`], optionally
+    /// fenced in a code block: with `fence: true` the language hint is the extension of
+    /// the file, and it can be given explicitly with `lang: <language>`, which implies
+    /// the fence.
+    ///
+    /// With a `glob` parameter, it includes all the matching files in order of name,
+    /// each prefixed by its path and fenced unless `fence: false` is given. See
+    /// [`FilePolicy::glob`] for the parameters.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::MissingFileArgument`] if neither a file name nor a glob
+    /// is provided.
+    /// Returns [`ExecuteError::FileOutsideProject`] if the file name or the glob is
+    /// absolute, leaves the project root or points into the `.vespe` directory.
+    /// Returns [`ExecuteError::IoError`] if a file cannot be read.
+    fn mono(inputs: StaticPolicyMonoInput) -> Result<StaticPolicyMonoResult> {
+        let (mut result, residual) = StaticPolicyMonoResult::from_inputs(inputs);
+        let tag = match residual.container {
+            Container::Tag(tag) => tag,
+            _ => {
+                panic!("!?!?!? cannot be anchor in static tag !?!?!?"); // better error TODO
+            }
+        };
+        let content = match residual.parameters.get_as_string_only("glob") {
+            Some(pattern) => Self::glob(residual.worker, &pattern, residual.parameters)?,
+            None => {
+                let file_name = residual
+                    .arguments
+                    .arguments
+                    .first()
+                    .ok_or(ExecuteError::MissingFileArgument { range: tag.range })?
+                    .value
+                    .clone();
+                let content = residual.worker.read_project_file(&file_name)?;
+                Self::fence(&file_name, &content, residual.parameters, false)
+            }
+        };
+        if !content.is_empty() {
+            result.collector = result.collector.push_item(ModelContentItem::user(&content));
+        }
+        Ok(result)
+    }
+}
+
+impl FilePolicy {
+    /// Returns the content of the project files matching `pattern`.
+    ///
+    /// The following parameters select the files:
+    /// - `exclude: <pattern>` (or an array of patterns) skips the matching files;
+    /// - `gitignore: false` includes the files ignored by git, skipped by default;
+    /// - `max_file_size: <bytes>` skips larger files;
+    /// - `max_total_size: <bytes>` skips the files that would exceed it in total.
+    fn glob(worker: &Worker, pattern: &str, parameters: &Parameters) -> Result<String> {
+        let exclude = match parameters.get("exclude") {
+            None => vec![],
+            Some(JsonPlusEntity::Array(patterns)) => patterns.iter().collect(),
+            Some(pattern) => vec![pattern],
+        };
+        let exclude = exclude
+            .into_iter()
+            .map(|x| {
+                glob::Pattern::new(&x.to_prompt())
+                    .map_err(|e| ExecuteError::UnsupportedParameterValue(format!("exclude: {}", e)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let max_file_size = Self::size_from_parameters(parameters, "max_file_size")?
+            .unwrap_or(DEFAULT_MAX_FILE_SIZE);
+        let max_total_size = Self::size_from_parameters(parameters, "max_total_size")?
+            .unwrap_or(DEFAULT_MAX_TOTAL_SIZE);
+
+        let file_names = worker
+            .glob_project_files(pattern)?
+            .into_iter()
+            .filter(|x| !exclude.iter().any(|p| p.matches(x)))
+            .collect::<Vec<_>>();
+        let ignored = match parameters.get_as_bool("gitignore").unwrap_or(true) {
+            true => git_ignored_files(&worker.project_root(), &file_names)
+                .map_err(crate::utils::Error::from)?,
+            false => Default::default(),
+        };
+        let mut output = String::new();
+        let mut skipped = Vec::new();
+        let mut total_size = 0;
+        for file_name in file_names {
+            if ignored.contains(&file_name) {
+                skipped.push((file_name, SkipReason::Ignored));
+                continue;
+            }
+            // Sizes are checked before reading, so that large files are never loaded
+            let size = std::fs::metadata(worker.project_root().join(&file_name))?.len();
+            if size > max_file_size {
+                skipped.push((file_name, SkipReason::TooLarge(size)));
+                continue;
+            }
+            if total_size + size > max_total_size {
+                skipped.push((file_name, SkipReason::TotalTooLarge));
+                continue;
+            }
+            let content = match worker.read_project_file(&file_name) {
+                Ok(content) if !content.contains('\0') => content,
+                Ok(_) => {
+                    skipped.push((file_name, SkipReason::NotText));
+                    continue;
+                }
+                Err(e) if Self::is_not_text_error(&e) => {
+                    skipped.push((file_name, SkipReason::NotText));
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            total_size += size;
+            output.push_str(&format!("`{}`:\n", file_name));
+            output.push_str(&Self::fence(&file_name, &content, parameters, true));
+        }
+        if output.is_empty() && skipped.is_empty() {
+            tracing::warn!("No file matches '{}'", pattern);
+        }
+        if !skipped.is_empty() {
+            output.push_str(&format!("Skipped {} files:\n", skipped.len()));
+            for (file_name, reason) in skipped {
+                output.push_str(&format!("- `{}`: {}\n", file_name, reason));
+            }
+        }
+        Ok(output)
+    }
+
+    /// Returns whether `error`, returned reading a file, is due to content that is not
+    /// valid UTF-8.
+    fn is_not_text_error(error: &ExecuteError) -> bool {
+        let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+        while let Some(e) = source {
+            if let Some(e) = e.downcast_ref::<std::io::Error>() {
+                if e.kind() == std::io::ErrorKind::InvalidData {
+                    return true;
+                }
+            }
+            source = e.source();
+        }
+        false
+    }
+
+    /// Fences `content` in a code block if requested by the `fence` or `lang` parameters,
+    /// `fence` defaulting to `default_fence`.
+    ///
+    /// The fence is longer than any run of backticks in `content`, so that the content
+    /// cannot close it.
+    fn fence(
+        file_name: &str,
+        content: &str,
+        parameters: &Parameters,
+        default_fence: bool,
+    ) -> String {
+        let lang = match parameters.get_as_string_only("lang") {
+            Some(lang) => lang,
+            None if parameters.get_as_bool("fence").unwrap_or(default_fence) => {
+                Path::new(file_name)
+                    .extension()
+                    .map(|x| x.to_string_lossy().to_string())
+                    .unwrap_or_default()
+            }
+            None => return content.to_string(),
+        };
+        let longest_run = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+        let fence = "`".repeat(std::cmp::max(3, longest_run + 1));
+        let newline = if content.is_empty() || content.ends_with('\n') {
+            ""
+        } else {
+            "\n"
+        };
+        format!("{}{}\n{}{}{}\n", fence, lang, content, newline, fence)
+    }
+
+    /// Reads a non-negative size in bytes from parameter `name`.
+    fn size_from_parameters(parameters: &Parameters, name: &str) -> Result<Option<u64>> {
+        match parameters.get_as_integer_only(name) {
+            None => Ok(None),
+            Some(size) => u64::try_from(size).map(Some).map_err(|_| {
+                ExecuteError::UnsupportedParameterValue(format!("{}: {}", name, size))
+            }),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Implements the behavior for the dynamic `@foreach` tag.
+//!
+//! The `@foreach` tag instantiates a template once for each item of a list. The items
+//! are given by one of the following parameters:
+//! - `items: [...]`, a list of values;
+//! - `glob: "<pattern>"`, the names of the project files matching the pattern;
+//! - `lines: "<file>"`, the non-empty lines of a project file.
+//!
+//! The anchor of `@foreach` is filled with one `@inline` tag per item, passing the item
+//! to the template as `data`. Each `@inline` then becomes an anchor of its own, so the
+//! tags of every instance, like `@answer`, have their own state.
+
+use serde::{Deserialize, Serialize};
+
+use super::error::ExecuteError;
+use super::execute::Worker;
+use super::tag_inline::InlineStatus;
+use super::tags::{
+    Container, DynamicPolicy, DynamicPolicyMonoInput, DynamicPolicyMonoResult, DynamicState,
+};
+use super::Result;
+use crate::ast2::{CommandKind, JsonPlusEntity, JsonPlusObject, Parameters};
+
+/// Holds the persistent state for a `@foreach` anchor.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct ForeachState {
+    /// The current status of the `@foreach` anchor; it goes through the same
+    /// statuses as an `@inline` anchor.
+    pub status: InlineStatus,
+    /// The number of items the template has been instantiated for.
+    pub items: usize,
+}
+
+/// Implements the dynamic policy for the `@foreach` tag.
+pub struct ForeachPolicy;
+
+impl DynamicPolicy for ForeachPolicy {
+    /// The state object associated with this policy.
+    type State = ForeachState;
+
+    /// Executes a single step of the `@foreach` tag's lifecycle.
+    ///
+    /// - `JustCreated`: Lists the items, fills the anchor with one `@inline` tag per item
+    ///   and transitions the state to `Completed`. It triggers a new pass to process the
+    ///   injected tags.
+    /// - `Completed`: No action is taken.
+    /// - `Repeat`: Resets the state to `JustCreated` and clears the existing content, so
+    ///   that the items are listed again.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::MissingForeachArgument`] if the template is missing, and
+    /// [`ExecuteError::MissingForeachItems`] if no list of items is given.
+    fn mono(
+        inputs: DynamicPolicyMonoInput<Self::State>,
+    ) -> Result<DynamicPolicyMonoResult<Self::State>> {
+        tracing::debug!(
+            "tag_foreach::ForeachPolicy::mono\nState = {:?}",
+            inputs.state
+        );
+        let (mut result, mut residual) =
+            DynamicPolicyMonoResult::<Self::State>::from_inputs(inputs);
+        match (residual.container, residual.state.status) {
+            (Container::Tag(_) | Container::BeginAnchor(_, _), InlineStatus::JustCreated) => {
+                if !residual.readonly {
+                    let template = residual
+                        .arguments
+                        .arguments
+                        .first()
+                        .ok_or_else(|| ExecuteError::MissingForeachArgument {
+                            range: residual.arguments.range,
+                        })?
+                        .value
+                        .clone();
+                    let items = Self::items(residual.worker, residual.parameters)?;
+                    let data = match residual.parameters.get("data") {
+                        Some(JsonPlusEntity::Object(data)) => residual
+                            .worker
+                            .render_data(data, result.collector.variables())?,
+                        Some(_) => {
+                            return Err(ExecuteError::UnsupportedDataParameter {
+                                range: residual.parameters.range,
+                            });
+                        }
+                        None => JsonPlusObject::new(),
+                    };
+                    let mut output = String::new();
+                    for (index, item) in items.iter().enumerate() {
+                        let mut item_data = data.clone();
+                        item_data.insert("item".to_string(), item.clone());
+                        item_data.insert(
+                            "index".to_string(),
+                            JsonPlusEntity::Integer(index as i64 + 1),
+                        );
+                        item_data.insert(
+                            "count".to_string(),
+                            JsonPlusEntity::Integer(items.len() as i64),
+                        );
+                        output.push_str(&format!(
+                            "@{} {{ data: {} }} {}\n",
+                            CommandKind::Inline.to_string(),
+                            item_data.to_string(),
+                            template
+                        ));
+                    }
+                    residual.state.status = InlineStatus::Completed;
+                    residual.state.items = items.len();
+                    result.new_state = Some(residual.state);
+                    result.new_output = Some(output);
+                }
+                result.do_next_pass = true;
+            }
+            (Container::BeginAnchor(_, _), InlineStatus::Completed) => {
+                // Nothing to do
+            }
+            (Container::BeginAnchor(_, _), InlineStatus::Repeat) => {
+                // Reset state to list the items again in the next pass
+                if !residual.readonly {
+                    residual.state.status = InlineStatus::JustCreated;
+                    result.new_state = Some(residual.state);
+                    result.new_output = Some(String::new());
+                }
+                result.do_next_pass = true;
+            }
+            _ => {}
+        }
+        Ok(result)
+    }
+}
+
+impl ForeachPolicy {
+    /// Lists the items given by the `items`, `glob` or `lines` parameter.
+    ///
+    /// Files named by `glob` or `lines` must be project files outside `.vespe`, otherwise
+    /// [`ExecuteError::FileOutsideProject`] is returned.
+    fn items(worker: &Worker, parameters: &Parameters) -> Result<Vec<JsonPlusEntity>> {
+        if let Some(items) = parameters.get("items") {
+            return match items {
+                JsonPlusEntity::Array(items) => Ok(items.clone()),
+                x => Err(ExecuteError::UnsupportedParameterValue(format!(
+                    "items: {:?}",
+                    x
+                ))),
+            };
+        }
+        let names = if let Some(pattern) = parameters.get_as_string_only("glob") {
+            worker.glob_project_files(&pattern)?
+        } else if let Some(file) = parameters.get_as_string_only("lines") {
+            worker
+                .read_project_file(&file)?
+                .lines()
+                .map(str::trim)
+                .filter(|x| !x.is_empty())
+                .map(str::to_string)
+                .collect()
+        } else {
+            return Err(ExecuteError::MissingForeachItems {
+                range: parameters.range,
+            });
+        };
+        Ok(names
+            .into_iter()
+            .map(JsonPlusEntity::DoubleQuotedString)
+            .collect())
+    }
+}
+
+impl DynamicState for ForeachState {
+    fn status_indicator(&self) -> String {
+        self.status.to_string()
+    }
+}
@@ -0,0 +1,87 @@
+//! This module implements the policies of the `@if`, `@else` and `@endif` tags, which
+//! delimit conditional sections of a context. The text, tags and anchors in a branch
+//! that is not taken are neither collected into the `ModelContent` nor executed.
+//!
+//! The condition of `@if` compares a value looked up by name, among the stored variables,
+//! the `data` of the context and the defines of the execution:
+//! - `var: <name>` names the value;
+//! - `equals: <value>` (or an array of values) requires the value to be one of them;
+//!   without it, the value must be defined, non-empty and not `false`;
+//! - `not: true` negates the condition.
+use super::execute::Collector;
+use super::{ExecuteError, Result};
+
+use super::tags::{StaticPolicy, StaticPolicyMonoInput, StaticPolicyMonoResult};
+
+use crate::ast2::{JsonPlusEntity, Parameters};
+
+/// Implements the static policy for the `@if` tag.
+///
+/// The `@if` tag opens a conditional section in the [`Collector`], whose first branch is
+/// taken if the condition holds.
+pub struct IfPolicy;
+
+impl StaticPolicy for IfPolicy {
+    /// Opens a conditional section.
+    ///
+    /// Inside a branch not taken the condition is not evaluated, and the whole nested
+    /// section is skipped.
+    fn mono(inputs: StaticPolicyMonoInput) -> Result<StaticPolicyMonoResult> {
+        let (mut result, residual) = StaticPolicyMonoResult::from_inputs(inputs);
+        let taken = match result.collector.is_skipping() {
+            true => false,
+            false => condition_holds(&result.collector, residual.parameters)?,
+        };
+        result.collector = result.collector.open_condition(taken);
+        Ok(result)
+    }
+}
+
+/// Implements the static policy for the `@else` tag.
+///
+/// The `@else` tag enters the alternative branch of the innermost conditional section.
+pub struct ElsePolicy;
+
+impl StaticPolicy for ElsePolicy {
+    fn mono(inputs: StaticPolicyMonoInput) -> Result<StaticPolicyMonoResult> {
+        let (mut result, _residual) = StaticPolicyMonoResult::from_inputs(inputs);
+        result.collector = result.collector.else_condition()?;
+        Ok(result)
+    }
+}
+
+/// Implements the static policy for the `@endif` tag.
+///
+/// The `@endif` tag closes the innermost conditional section.
+pub struct EndIfPolicy;
+
+impl StaticPolicy for EndIfPolicy {
+    fn mono(inputs: StaticPolicyMonoInput) -> Result<StaticPolicyMonoResult> {
+        let (mut result, _residual) = StaticPolicyMonoResult::from_inputs(inputs);
+        result.collector = result.collector.close_condition()?;
+        Ok(result)
+    }
+}
+
+/// Evaluates the condition given by the parameters of an `@if` tag.
+///
+/// # Errors
+///
+/// Returns [`ExecuteError::MissingParameter`] if the `var` parameter is missing.
+fn condition_holds(collector: &Collector, parameters: &Parameters) -> Result<bool> {
+    let name = parameters
+        .get_as_string_only("var")
+        .ok_or_else(|| ExecuteError::MissingParameter("var".to_string()))?;
+    let value = collector
+        .lookup(&name)
+        .map(|x| x.to_prompt().trim().to_string());
+    let holds = match (value, parameters.get("equals")) {
+        (None, _) => false,
+        (Some(value), None) => !value.is_empty() && value != "false",
+        (Some(value), Some(JsonPlusEntity::Array(expected))) => {
+            expected.iter().any(|x| x.to_prompt().trim() == value)
+        }
+        (Some(value), Some(expected)) => expected.to_prompt().trim() == value,
+    };
+    Ok(holds != parameters.get_as_bool("not").unwrap_or(false))
+}
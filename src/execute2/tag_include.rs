@@ -55,25 +55,30 @@ impl StaticPolicy for IncludePolicy {
             .value
             .clone();
         let data = match residual.parameters.get("data") {
-            Some(JsonPlusEntity::Object(data)) => Some(data),
+            Some(JsonPlusEntity::Object(data)) => Some(
+                residual
+                    .worker
+                    .render_data(data, result.collector.variables())?,
+            ),
             Some(_) => {
                 return Err(ExecuteError::UnsupportedDataParameter { range: tag.range });
             }
             None => None,
         };
-        result.collector =
-            match residual
-                .worker
-                ._execute(result.collector, &included_context_name, 0, data)?
-            {
-                Some(collector) => collector,
-                None => {
-                    return Err(ExecuteError::IncludeExecutionFailed {
-                        context: included_context_name,
-                        range: tag.range,
-                    });
-                }
-            };
+        result.collector = match residual.worker._execute(
+            result.collector,
+            &included_context_name,
+            0,
+            data.as_ref(),
+        )? {
+            Some(collector) => collector,
+            None => {
+                return Err(ExecuteError::IncludeExecutionFailed {
+                    context: included_context_name,
+                    range: tag.range,
+                });
+            }
+        };
         Ok(result)
     }
 }
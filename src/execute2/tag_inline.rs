@@ -9,11 +9,12 @@
 use serde::{Deserialize, Serialize};
 
 use super::error::ExecuteError;
+use super::execute::Worker;
 use super::tags::{
     Container, DynamicPolicy, DynamicPolicyMonoInput, DynamicPolicyMonoResult, DynamicState,
 };
 use super::Result;
-use crate::ast2::JsonPlusEntity;
+use crate::ast2::{JsonPlusEntity, Range};
 use std::str::FromStr;
 
 /// Represents the execution status of an `@inline` tag.
@@ -118,6 +119,23 @@ impl DynamicPolicy for InlinePolicy {
         tracing::debug!("tag_inline::InlinePolicy::mono\nState = {:?}", inputs.state);
         let (mut result, mut residual) =
             DynamicPolicyMonoResult::<Self::State>::from_inputs(inputs);
+        if let (Container::EndAnchor(a0, a1), Some(name)) = (
+            &residual.container,
+            residual.parameters.get_as_string_only("store"),
+        ) {
+            // Store the inlined content, as it is in the document
+            let content = Worker::get_range(
+                residual.document,
+                &Range {
+                    begin: a0.range.end,
+                    end: a1.range.begin,
+                },
+            )?;
+            result.collector = result.collector.set_variable(
+                &name,
+                JsonPlusEntity::DoubleQuotedString(content.trim().to_string()),
+            );
+        }
         match (residual.container, residual.state.status) {
             (Container::Tag(_) | Container::BeginAnchor(_, _), InlineStatus::JustCreated) => {
                 if !residual.readonly {
@@ -149,7 +167,10 @@ impl DynamicPolicy for InlinePolicy {
                     );
                     let context = match residual.parameters.get("data") {
                         Some(JsonPlusEntity::Object(data)) => {
-                            residual.worker.process_context_with_data(context, data)?
+                            let data = residual
+                                .worker
+                                .render_data(data, result.collector.variables())?;
+                            residual.worker.process_context_with_data(context, &data)?
                         }
                         Some(_) => {
                             return Err(ExecuteError::UnsupportedDataParameter {
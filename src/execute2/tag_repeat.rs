@@ -16,7 +16,9 @@
 use super::{ExecuteError, Result};
 
 use super::tag_answer::{AnswerState, AnswerStatus};
+use super::tag_foreach::ForeachState;
 use super::tag_inline::{InlineState, InlineStatus};
+use super::tag_shell::ShellState;
 use super::tags::{Container, StaticPolicy, StaticPolicyMonoInput, StaticPolicyMonoResult};
 
 use crate::ast2::CommandKind;
@@ -97,7 +99,7 @@ impl StaticPolicy for RepeatPolicy {
         match result.collector.anchor_stack().last() {
             Some(anchor) => {
                 let is_anchor_repeatable = match anchor.command {
-                    CommandKind::Answer => {
+                    CommandKind::Answer | CommandKind::Derive | CommandKind::Compact => {
                         let mut answer_state = residual
                             .worker
                             .load_state::<AnswerState>(anchor.command, &anchor.uuid)?;
@@ -123,6 +125,32 @@ impl StaticPolicy for RepeatPolicy {
                         )?;
                         true
                     }
+                    CommandKind::Foreach => {
+                        let mut foreach_state = residual
+                            .worker
+                            .load_state::<ForeachState>(anchor.command, &anchor.uuid)?;
+                        foreach_state.status = InlineStatus::Repeat;
+                        residual.worker.save_state::<ForeachState>(
+                            anchor.command,
+                            &anchor.uuid,
+                            &foreach_state,
+                            None,
+                        )?;
+                        true
+                    }
+                    CommandKind::Shell => {
+                        let mut shell_state = residual
+                            .worker
+                            .load_state::<ShellState>(anchor.command, &anchor.uuid)?;
+                        shell_state.status = InlineStatus::Repeat;
+                        residual.worker.save_state::<ShellState>(
+                            anchor.command,
+                            &anchor.uuid,
+                            &shell_state,
+                            None,
+                        )?;
+                        true
+                    }
                     _ => false,
                 };
                 if !is_anchor_repeatable {
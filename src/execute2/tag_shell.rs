@@ -0,0 +1,173 @@
+//! Implements the behavior for the dynamic `@shell` tag.
+//!
+//! The `@shell` tag runs a local command in the project root and injects its stdout into
+//! its anchor, where it is seen by the model as user content. The exit code of the command
+//! is kept in the state of the anchor; a command exiting unsuccessfully does not stop the
+//! execution, as its output (e.g. of failing tests) is often what the prompt needs.
+//!
+//! The command runs again on `@repeat`, or on every run with `dynamic: true`, in which case
+//! the anchor is updated whenever the output changes. It runs at most once per run, however
+//! many passes the run takes.
+//!
+//! The content of the anchor is verbatim (see [`CommandKind::is_verbatim`]): it is neither
+//! rendered as a template nor parsed for tags, but `@repeat`.
+//!
+//! [`CommandKind::is_verbatim`]: crate::ast2::CommandKind::is_verbatim
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::error::ExecuteError;
+use super::execute::{Collector, Worker};
+use super::tag_answer::AnswerPolicy;
+use super::tag_inline::InlineStatus;
+use super::tags::{
+    Container, DynamicPolicy, DynamicPolicyMonoInput, DynamicPolicyMonoResult, DynamicState,
+};
+use super::Result;
+use crate::agent::shell::run_command;
+use crate::ast2::Parameters;
+
+/// Holds the persistent state for a `@shell` anchor.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct ShellState {
+    /// The current status of the `@shell` anchor; it goes through the same statuses
+    /// as an `@inline` anchor.
+    pub status: InlineStatus,
+    /// The exit code of the latest run, `None` if the command was terminated by a signal.
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    /// What the latest run wrote on stderr, unless it is part of the output.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stderr: String,
+    /// The normalized hash of the injected output.
+    #[serde(default)]
+    pub output_hash: String,
+}
+
+/// Implements the dynamic policy for the `@shell` tag.
+pub struct ShellPolicy;
+
+impl DynamicPolicy for ShellPolicy {
+    /// The state object associated with this policy.
+    type State = ShellState;
+
+    /// Executes a single step of the `@shell` tag's lifecycle.
+    ///
+    /// - `JustCreated`: Turns the tag into an anchor, then runs the command, injects its
+    ///   output and transitions the state to `Completed`. It triggers a new pass to process
+    ///   the injected content.
+    /// - `Completed`: With `dynamic: true`, runs the command again, once per execution, and
+    ///   injects its output if it changed.
+    /// - `Repeat`: Resets the state to `JustCreated` and clears the existing content,
+    ///   triggering a new pass to run the command again.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExecuteError::MissingParameter`] if the `command` parameter is missing,
+    /// and [`ExecuteError::ShellError`] if the command cannot be run or times out.
+    fn mono(
+        inputs: DynamicPolicyMonoInput<Self::State>,
+    ) -> Result<DynamicPolicyMonoResult<Self::State>> {
+        tracing::debug!("tag_shell::ShellPolicy::mono\nState = {:?}", inputs.state);
+        let (mut result, mut residual) =
+            DynamicPolicyMonoResult::<Self::State>::from_inputs(inputs);
+        match (residual.container, residual.state.status.clone()) {
+            (Container::Tag(_), InlineStatus::JustCreated) => {
+                // Create the anchor, the command runs once its uuid is known
+                result.new_state = Some(residual.state);
+                result.do_next_pass = true;
+            }
+            (Container::BeginAnchor(a0, _), InlineStatus::JustCreated) => {
+                if !residual.readonly {
+                    residual.worker.record_command_run(&a0.uuid);
+                    let output =
+                        Self::run(residual.worker, residual.parameters, &mut residual.state)?;
+                    residual.state.status = InlineStatus::Completed;
+                    result.new_state = Some(residual.state);
+                    result.new_output = Some(output);
+                }
+                result.do_next_pass = true;
+            }
+            (Container::BeginAnchor(a0, _), InlineStatus::Completed) => {
+                let is_dynamic = residual.parameters.get_as_bool("dynamic").unwrap_or(false);
+                // The command runs at most once per execution, not once per pass
+                if is_dynamic && !residual.readonly && residual.worker.record_command_run(&a0.uuid)
+                {
+                    let previous_hash = residual.state.output_hash.clone();
+                    let output =
+                        Self::run(residual.worker, residual.parameters, &mut residual.state)?;
+                    if residual.state.output_hash != previous_hash {
+                        // Output changed, inject it
+                        result.new_state = Some(residual.state);
+                        result.new_output = Some(output);
+                        result.do_next_pass = true;
+                    }
+                }
+            }
+            (Container::BeginAnchor(_, _), InlineStatus::Repeat) => {
+                // Reset state to force running the command in the next pass
+                if !residual.readonly {
+                    residual.state.status = InlineStatus::JustCreated;
+                    result.new_state = Some(residual.state);
+                    result.new_output = Some(String::new());
+                }
+                result.do_next_pass = true;
+            }
+            _ => {}
+        }
+        Ok(result)
+    }
+}
+
+impl ShellPolicy {
+    /// Runs the command of the tag, recording its outcome in `state`, and returns the
+    /// output to inject.
+    ///
+    /// The command is run by the platform shell unless `shell: false` is given; with
+    /// `stderr: true` its stderr is appended to the output.
+    fn run(worker: &Worker, parameters: &Parameters, state: &mut ShellState) -> Result<String> {
+        let command = parameters
+            .get_as_string_only("command")
+            .ok_or_else(|| ExecuteError::MissingParameter("command".to_string()))?;
+        let use_shell = parameters.get_as_bool("shell").unwrap_or(true);
+        let timeout = AnswerPolicy::duration_from_parameters(parameters, "timeout")?;
+        let output = run_command(
+            &command,
+            use_shell,
+            "",
+            &BTreeMap::new(),
+            Some(&worker.project_root()),
+            timeout,
+            |_| {},
+        )
+        .map_err(|e| ExecuteError::ShellError(e.to_string()))?;
+        if !output.success() {
+            tracing::warn!(
+                "Command '{}' exited with code {:?}",
+                command,
+                output.exit_code
+            );
+        }
+        let mut text = output.stdout;
+        state.stderr = match parameters.get_as_bool("stderr").unwrap_or(false) {
+            true => {
+                text.push_str(&output.stderr);
+                String::new()
+            }
+            false => output.stderr,
+        };
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        state.exit_code = output.exit_code;
+        state.output_hash = Collector::normalized_hash(&text);
+        Ok(text)
+    }
+}
+
+impl DynamicState for ShellState {
+    fn status_indicator(&self) -> String {
+        self.status.to_string()
+    }
+}
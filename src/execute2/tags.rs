@@ -11,12 +11,18 @@ use super::execute::Collector;
 use super::execute::Worker;
 
 use super::tag_answer::AnswerPolicy;
+use super::tag_compact::CompactPolicy;
+use super::tag_derive::DerivePolicy;
 use super::tag_done::DonePolicy;
+use super::tag_file::FilePolicy;
+use super::tag_foreach::ForeachPolicy;
 use super::tag_forget::ForgetPolicy;
+use super::tag_if::{ElsePolicy, EndIfPolicy, IfPolicy};
 use super::tag_include::IncludePolicy;
 use super::tag_inline::InlinePolicy;
 use super::tag_repeat::RepeatPolicy;
 use super::tag_set::SetPolicy;
+use super::tag_shell::ShellPolicy;
 use super::tag_task::TaskPolicy;
 
 use crate::ast2::{Anchor, Arguments, CommandKind, Parameters, Range, Tag};
@@ -769,6 +775,14 @@ impl TagBehaviorDispatch {
             CommandKind::Forget => Ok(Box::new(StaticTagBehavior(ForgetPolicy))),
             CommandKind::Task => Ok(Box::new(DynamicTagBehavior(TaskPolicy))),
             CommandKind::Done => Ok(Box::new(StaticTagBehavior(DonePolicy))),
+            CommandKind::If => Ok(Box::new(StaticTagBehavior(IfPolicy))),
+            CommandKind::Else => Ok(Box::new(StaticTagBehavior(ElsePolicy))),
+            CommandKind::EndIf => Ok(Box::new(StaticTagBehavior(EndIfPolicy))),
+            CommandKind::Foreach => Ok(Box::new(DynamicTagBehavior(ForeachPolicy))),
+            CommandKind::Shell => Ok(Box::new(DynamicTagBehavior(ShellPolicy))),
+            CommandKind::File => Ok(Box::new(StaticTagBehavior(FilePolicy))),
+            CommandKind::Derive => Ok(Box::new(DynamicTagBehavior(DerivePolicy))),
+            CommandKind::Compact => Ok(Box::new(DynamicTagBehavior(CompactPolicy))),
             _ => Err(ExecuteError::UnsupportedCommand(command)),
         }
     }
@@ -0,0 +1,67 @@
+use super::test_utils::{run_context, write_context};
+use super::ExecuteOptions;
+use crate::agent::usage::UsageTally;
+use crate::config::Budget;
+use std::sync::Arc;
+
+const DOCUMENT: &str = r#"@set { provider: { kind: mock, replies: ["Paris.", "Rome."] } }
+What is the capital of France?
+@answer
+And of Italy?
+@answer
+"#;
+
+fn options(budget: Budget) -> (ExecuteOptions, Arc<UsageTally>) {
+    let tally = Arc::new(UsageTally::default());
+    let options = ExecuteOptions {
+        usage: Some(tally.clone()),
+        budget,
+        ..Default::default()
+    };
+    (options, tally)
+}
+
+#[test]
+fn test_calls_budget_leaves_answers_pending() {
+    let dir = tempfile::tempdir().unwrap();
+    let context_path = write_context(dir.path(), "main.md", DOCUMENT);
+
+    let (limited, tally) = options(Budget {
+        max_calls_per_run: Some(1),
+        ..Default::default()
+    });
+    run_context(dir.path(), "main.md", limited).unwrap();
+
+    let document = std::fs::read_to_string(&context_path).unwrap();
+    assert!(document.contains("\nParis.\n"));
+    assert!(!document.contains("\nRome.\n"));
+    assert!(document.contains("pending"));
+    assert_eq!(tally.calls(), 1);
+    assert_eq!(tally.pending(), 1);
+
+    // The mock script starts over in a new run: its first reply answers the pending query
+    let (unlimited, tally) = options(Budget::default());
+    run_context(dir.path(), "main.md", unlimited).unwrap();
+
+    let document = std::fs::read_to_string(&context_path).unwrap();
+    assert_eq!(document.matches("\nParis.\n").count(), 2);
+    assert_eq!(tally.pending(), 0);
+}
+
+#[test]
+fn test_prompt_chars_budget_from_parameters() {
+    let dir = tempfile::tempdir().unwrap();
+    let context_path = write_context(
+        dir.path(),
+        "main.md",
+        &DOCUMENT.replace("] } }", "] }, max_prompt_chars: 10 }"),
+    );
+
+    let (options, tally) = options(Budget::default());
+    run_context(dir.path(), "main.md", options).unwrap();
+
+    let document = std::fs::read_to_string(&context_path).unwrap();
+    assert!(!document.contains("\nParis.\n"));
+    assert_eq!(tally.calls(), 0);
+    assert_eq!(tally.pending(), 2);
+}
@@ -0,0 +1,74 @@
+use super::test_utils::{run_context, write_context};
+use super::ExecuteOptions;
+use crate::agent::cache::ResponseCache;
+use std::path::Path;
+use std::sync::Arc;
+
+fn options(cache: &Path) -> ExecuteOptions {
+    ExecuteOptions {
+        cache: Some(Arc::new(ResponseCache::open(cache))),
+        ..Default::default()
+    }
+}
+
+/// Runs a new context asking the capital of France to the mock provider scripted by
+/// `script`, which replies `reply`, and returns the resulting document.
+fn ask(root: &Path, name: &str, script: &Path, reply: &str, options: ExecuteOptions) -> String {
+    std::fs::write(script, format!("[\"{}\"]", reply)).unwrap();
+    let context_path = write_context(
+        root,
+        name,
+        &format!(
+            "What is the capital of France?\n@answer {{ provider: \"mock:{}\" }}\n",
+            script.display()
+        ),
+    );
+    run_context(root, name, options).unwrap();
+    std::fs::read_to_string(context_path).unwrap()
+}
+
+#[test]
+fn test_identical_query_served_from_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = dir.path().join("cache");
+    let script = dir.path().join("replies.json");
+
+    let first = ask(dir.path(), "first.md", &script, "Paris.", options(&cache));
+    assert!(first.contains("\nParis.\n"));
+    assert_eq!(ResponseCache::open(&cache).stats().unwrap().entries, 1);
+
+    let second = ask(dir.path(), "second.md", &script, "Lyon.", options(&cache));
+    assert!(second.contains("\nParis.\n"));
+
+    let uncached = ask(
+        dir.path(),
+        "third.md",
+        &script,
+        "Lyon.",
+        ExecuteOptions::default(),
+    );
+    assert!(uncached.contains("\nLyon.\n"));
+}
+
+#[test]
+fn test_cache_disabled_per_tag() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = dir.path().join("cache");
+    let script = dir.path().join("replies.json");
+
+    ask(dir.path(), "first.md", &script, "Paris.", options(&cache));
+
+    std::fs::write(&script, "[\"Lyon.\"]").unwrap();
+    let context_path = write_context(
+        dir.path(),
+        "second.md",
+        &format!(
+            "What is the capital of France?\n@answer {{ provider: \"mock:{}\", cache: false }}\n",
+            script.display()
+        ),
+    );
+    run_context(dir.path(), "second.md", options(&cache)).unwrap();
+
+    let document = std::fs::read_to_string(context_path).unwrap();
+    assert!(document.contains("\nLyon.\n"));
+}
@@ -0,0 +1,103 @@
+use super::test_utils::{run_context, write_context};
+use super::{execute_context_with_options, ExecuteError, ExecuteOptions};
+use crate::agent::cassette::{Cassette, CassetteMode};
+use crate::utils::file::ProjectFileAccessor;
+use crate::utils::path::ProjectPathResolver;
+use std::sync::Arc;
+
+const DOCUMENT: &str = r#"What is the capital of France?
+@answer { provider: { kind: mock, replies: ["Paris."] } }
+"#;
+
+const REPLAY_DOCUMENT: &str = r#"What is the capital of France?
+@answer { provider: { kind: mock, replies: [] } }
+"#;
+
+fn options(cassette: &std::path::Path, mode: CassetteMode) -> ExecuteOptions {
+    ExecuteOptions {
+        cassette: Some(Arc::new(Cassette::open(cassette, mode).unwrap())),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_record_then_replay() {
+    let dir = tempfile::tempdir().unwrap();
+    let cassette = dir.path().join("cassette.json");
+
+    write_context(dir.path(), "main.md", DOCUMENT);
+    run_context(
+        dir.path(),
+        "main.md",
+        options(&cassette, CassetteMode::Record),
+    )
+    .unwrap();
+    assert!(std::fs::read_to_string(&cassette)
+        .unwrap()
+        .contains("Paris."));
+
+    // The mock has no replies left to give: the answer must come from the cassette
+    let context_path = write_context(dir.path(), "main.md", REPLAY_DOCUMENT);
+    run_context(
+        dir.path(),
+        "main.md",
+        options(&cassette, CassetteMode::Replay),
+    )
+    .unwrap();
+    assert!(std::fs::read_to_string(&context_path)
+        .unwrap()
+        .contains("\nParis.\n"));
+}
+
+#[test]
+fn test_replay_miss_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let cassette = dir.path().join("cassette.json");
+    std::fs::write(&cassette, "{}").unwrap();
+
+    write_context(dir.path(), "main.md", DOCUMENT);
+    let result = run_context(
+        dir.path(),
+        "main.md",
+        options(&cassette, CassetteMode::Replay),
+    );
+
+    assert!(matches!(result, Err(ExecuteError::CassetteMiss(_))));
+}
+
+#[test]
+fn test_replay_requires_cassette() {
+    let dir = tempfile::tempdir().unwrap();
+    let cassette = dir.path().join("cassette.json");
+
+    assert!(Cassette::open(&cassette, CassetteMode::Replay).is_err());
+    assert!(Cassette::open(&cassette, CassetteMode::Record).is_ok());
+}
+
+#[test]
+fn test_record_through_file_accessor() {
+    let dir = tempfile::tempdir().unwrap();
+    let cassette = dir.path().join("cassette.json");
+    write_context(dir.path(), "main.md", DOCUMENT);
+    let file_access = Arc::new(ProjectFileAccessor::new(dir.path(), None));
+
+    execute_context_with_options(
+        file_access.clone(),
+        Arc::new(ProjectPathResolver::new(
+            dir.path().to_path_buf(),
+            Vec::new(),
+            None,
+        )),
+        "main.md",
+        None,
+        options(&cassette, CassetteMode::Record),
+    )
+    .unwrap();
+
+    // The cassette is committed with the other files of the run
+    assert!(file_access.modified_files().unwrap().contains(&cassette));
+    assert!(file_access
+        .modified_files_comments()
+        .unwrap()
+        .contains("cassette"));
+}
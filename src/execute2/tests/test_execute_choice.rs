@@ -0,0 +1,85 @@
+use super::test_utils::{answer_state, execute_document, write_context};
+
+#[test]
+fn test_choice_keeps_reasoning() {
+    let dir = tempfile::tempdir().unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"Which language should we use?
+@answer { provider: { kind: mock, replies: ["CHOICE: §Rust\nREASONING: Safety matters most."] }, choose: { Rust: "We use Rust.", Python: "We use Python." }, with_reasoning: true }
+"#,
+    );
+
+    assert!(document.contains("\nWe use Rust.\n\nSafety matters most.\n"));
+    let state = answer_state(dir.path(), "main.md");
+    assert_eq!(state.choices, vec!["Rust"]);
+    assert_eq!(state.reasoning, "Safety matters most.");
+}
+
+#[test]
+fn test_choose_many_concatenates_values() {
+    let dir = tempfile::tempdir().unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"Label this issue: the docs example crashes.
+@answer { provider: { kind: mock, replies: ["CHOICES: §bug, §docs\nREASONING: It crashes, in the docs."] }, choose: { bug: "label: bug", docs: "label: docs", feature: "label: feature" }, choose_many: true, max_choices: 2 }
+"#,
+    );
+
+    assert!(document.contains("\nlabel: bug\nlabel: docs\n"));
+    assert!(!document.contains("label: feature\n<!--"));
+    let state = answer_state(dir.path(), "main.md");
+    assert_eq!(state.choices, vec!["bug", "docs"]);
+    assert!(state.query.contains("between 1 and 2 options"));
+}
+
+#[test]
+fn test_choose_many_enforces_counts() {
+    let dir = tempfile::tempdir().unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"Label this issue: the docs example crashes.
+@answer { provider: { kind: mock, replies: ["CHOICES: §bug"] }, choose: { bug: "label: bug", docs: "label: docs", feature: "label: feature" }, choose_many: true, min_choices: 2 }
+"#,
+    );
+
+    assert!(document.contains("\n[Too few choices were taken - CHOICES: §bug\n]."));
+    let state = answer_state(dir.path(), "main.md");
+    assert_eq!(state.choices, vec!["bug"]);
+}
+
+#[test]
+fn test_choice_branches_into_context() {
+    let dir = tempfile::tempdir().unwrap();
+    let bug_path = write_context(
+        dir.path(),
+        "branch/bug.md",
+        r#"Plan a fix for this {{severity}} severity bug.
+@answer { provider: { kind: mock, replies: ["Reproduce it first."] } }
+"#,
+    );
+    write_context(
+        dir.path(),
+        "branch/feature.md",
+        "Schedule the feature for the next release.\n",
+    );
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"Is this report about a bug or a feature request?
+@answer { provider: { kind: mock, replies: ["CHOICE: §bug"] }, choose: { bug: { context: "branch/bug.md", data: { severity: high } }, feature: { context: "branch/feature.md" } } }
+"#,
+    );
+
+    assert!(
+        document.contains("\nPlan a fix for this high severity bug.\n\nReproduce it first.\n<!--")
+    );
+    assert!(!document.contains("\nSchedule the feature"));
+    // The branch is executed in its own context
+    assert!(std::fs::read_to_string(&bug_path)
+        .unwrap()
+        .contains("\nReproduce it first.\n"));
+}
@@ -0,0 +1,53 @@
+use super::test_utils::execute_document;
+
+#[test]
+fn test_compact_replaces_history() {
+    let dir = tempfile::tempdir().unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"@set { provider: { kind: mock, replies: ["Use tabs.", "We chose tabs."] } }
+Tabs or spaces?
+@answer
+@compact
+What did we choose?
+@answer { provider: { kind: mock, echo: true } }
+"#,
+    );
+
+    // The summary is asked on the whole history
+    let compact = document.find("<!-- compact-").unwrap();
+    assert!(document[..compact].contains("\nTabs or spaces?\n"));
+    assert!(document[compact..].contains("\nWe chose tabs.\n"));
+    // Then it replaces the history
+    assert!(document.contains("User:\nWe chose tabs.\n\nWhat did we choose?\n"));
+    assert_eq!(document.matches("Tabs or spaces?").count(), 1);
+}
+
+#[test]
+fn test_compact_follows_history() {
+    let dir = tempfile::tempdir().unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"@set { provider: { kind: mock, replies: ["First summary."] } }
+Day one.
+@compact { instruction: "Summarize the diary." }
+"#,
+    );
+    assert!(document.contains("\nFirst summary.\n"));
+
+    // Unchanged history, the summary is kept
+    let document = document.replace("First summary.\"", "Second summary.\"");
+    let document = execute_document(dir.path(), "main.md", &document);
+    assert!(document.contains("\nFirst summary.\n"));
+
+    // Then summarized again when the history changes
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        &document.replace("Day one.\n", "Day one.\nDay two.\n"),
+    );
+    assert!(document.contains("\nSecond summary.\n"));
+    assert!(!document.contains("\nFirst summary.\n"));
+}
@@ -0,0 +1,108 @@
+use super::test_utils::{execute_document, run_context, write_context};
+use super::{collect_context, ExecuteError, ExecuteOptions};
+use crate::utils::file::ProjectFileAccessor;
+use crate::utils::path::ProjectPathResolver;
+use std::sync::Arc;
+
+#[test]
+fn test_derive_follows_source_file() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("design.md"), "Tags become anchors.\n").unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"@set { provider: { kind: mock, replies: ["First summary."] } }
+Not part of the source.
+@derive { file: design.md, instruction: "Summarize it." }
+"#,
+    );
+    assert!(document.contains("\nFirst summary.\n"));
+
+    // Unchanged source, nothing is derived again
+    let document = document.replace("First summary.\"", "Second summary.\"");
+    let document = execute_document(dir.path(), "main.md", &document);
+    assert!(document.contains("\nFirst summary.\n"));
+
+    std::fs::write(dir.path().join("design.md"), "Anchors keep state.\n").unwrap();
+    let document = execute_document(dir.path(), "main.md", &document);
+    assert!(document.contains("\nSecond summary.\n"));
+    assert!(!document.contains("\nFirst summary.\n"));
+}
+
+#[test]
+fn test_derive_from_anchor_and_context() {
+    let dir = tempfile::tempdir().unwrap();
+    write_context(dir.path(), "notes.md", "Some notes.\n");
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"@set { provider: { kind: mock, replies: ["Draft text."] } }
+@answer
+"#,
+    );
+    let uuid = document
+        .split("<!-- answer-")
+        .nth(1)
+        .unwrap()
+        .split(":begin")
+        .next()
+        .unwrap()
+        .to_string();
+
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        &format!(
+            "{}@derive {{ anchor: \"{}\", instruction: \"Shorten it.\", provider: {{ kind: mock, echo: true }} }}\n@derive {{ context: notes.md, instruction: \"List the notes.\", provider: {{ kind: mock, echo: true }} }}\n",
+            document, uuid
+        ),
+    );
+
+    assert!(document.contains("User:\nDraft text.\n\nShorten it.\n"));
+    assert!(document.contains("User:\nSome notes.\n\nList the notes.\n"));
+    assert_eq!(document.matches("<!-- derive-").count(), 4);
+}
+
+#[test]
+fn test_derive_collect_does_not_execute_source() {
+    let dir = tempfile::tempdir().unwrap();
+    write_context(dir.path(), "notes.md", "Some notes.\n");
+    execute_document(
+        dir.path(),
+        "main.md",
+        "@derive { context: notes.md, instruction: \"List the notes.\", provider: { kind: mock, echo: true } }\n",
+    );
+    let notes = "Some notes.\n@answer { provider: { kind: mock, echo: true } }\n";
+    let notes_path = write_context(dir.path(), "notes.md", notes);
+
+    collect_context(
+        Arc::new(ProjectFileAccessor::new(dir.path(), None)),
+        Arc::new(ProjectPathResolver::new(
+            dir.path().to_path_buf(),
+            Vec::new(),
+            None,
+        )),
+        "main.md",
+        None,
+    )
+    .unwrap();
+
+    // Checking the source for changes only collects it
+    assert_eq!(std::fs::read_to_string(&notes_path).unwrap(), notes);
+}
+
+#[test]
+fn test_derive_missing_source() {
+    let dir = tempfile::tempdir().unwrap();
+    write_context(
+        dir.path(),
+        "main.md",
+        "@derive { instruction: \"Summarize.\", provider: { kind: mock, echo: true } }\n",
+    );
+
+    let result = run_context(dir.path(), "main.md", ExecuteOptions::default());
+    assert!(matches!(
+        result,
+        Err(ExecuteError::MissingDeriveSource { .. })
+    ));
+}
@@ -0,0 +1,131 @@
+use super::test_utils::{execute_document, run_context, write_context};
+use super::{ExecuteError, ExecuteOptions};
+
+#[test]
+fn test_file_is_raw() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("src")).unwrap();
+    std::fs::write(
+        dir.path().join("src/lib.rs"),
+        "// {{not_a_variable}}\n// @answer\nfn main() {}",
+    )
+    .unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"Explain this code:
+@file { fence: true } src/lib.rs
+@answer { provider: { kind: mock, echo: true } }
+"#,
+    );
+
+    // The file is neither rendered nor parsed, and the answer echoes it
+    assert!(document.contains("\n```rs\n// {{not_a_variable}}\n// @answer\nfn main() {}\n```\n"));
+    assert!(document.contains("@file { fence: true } src/lib.rs\n"));
+    assert_eq!(document.matches("<!-- answer-").count(), 2);
+}
+
+#[test]
+fn test_file_fence_is_longer_than_content_backticks() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("notes.md"), "Run:\n```sh\nls\n```\n").unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"@file { lang: markdown } notes.md
+@file notes.md
+@answer { provider: { kind: mock, echo: true } }
+"#,
+    );
+
+    assert!(document.contains("\n````markdown\nRun:\n```sh\nls\n```\n````\n\nRun:\n```sh\n"));
+}
+
+#[test]
+fn test_file_outside_project() {
+    let dir = tempfile::tempdir().unwrap();
+    write_context(dir.path(), "secret.md", "Secret\n");
+    for name in [
+        ".vespe/contexts/secret.md",
+        "./.vespe/x",
+        "../x",
+        "/etc/hosts",
+    ] {
+        write_context(dir.path(), "main.md", &format!("@file {}\n", name));
+        let result = run_context(dir.path(), "main.md", ExecuteOptions::default());
+        assert!(
+            matches!(result, Err(ExecuteError::FileOutsideProject { .. })),
+            "{}: {:?}",
+            name,
+            result
+        );
+    }
+}
+
+#[test]
+fn test_file_glob() {
+    let dir = tempfile::tempdir().unwrap();
+    git2::Repository::init(dir.path()).unwrap();
+    std::fs::create_dir_all(dir.path().join("src/gen")).unwrap();
+    let files: [(&str, &[u8]); 8] = [
+        (".gitignore", b"gen/\n*.log\n"),
+        ("src/b.rs", b"fn b() {}\n"),
+        ("src/a.rs", b"// {{a}}\nfn a() {}\n"),
+        ("src/a_test.rs", b"fn test() {}\n"),
+        ("src/big.rs", &[b'x'; 200]),
+        ("src/blob.bin", b"\0\x01\x02"),
+        ("src/latin1.txt", b"caf\xe9\n"),
+        ("src/run.log", b"log\n"),
+    ];
+    for (name, content) in files {
+        std::fs::write(dir.path().join(name), content).unwrap();
+    }
+    std::fs::write(dir.path().join("src/gen/c.rs"), "fn c() {}\n").unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"@file { glob: "src/**/*", exclude: "*_test.rs", max_file_size: 100 }
+@answer { provider: { kind: mock, echo: true } }
+"#,
+    );
+
+    assert!(document.contains(
+        r#"
+`src/a.rs`:
+```rs
+// {{a}}
+fn a() {}
+```
+`src/b.rs`:
+```rs
+fn b() {}
+```
+Skipped 5 files:
+- `src/big.rs`: too large, 200 bytes
+- `src/blob.bin`: not a text file
+- `src/gen/c.rs`: ignored by git
+- `src/latin1.txt`: not a text file
+- `src/run.log`: ignored by git
+"#
+    ));
+    assert!(!document.contains("a_test.rs"));
+}
+
+#[test]
+fn test_file_glob_total_size() {
+    let dir = tempfile::tempdir().unwrap();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        std::fs::write(dir.path().join(name), "0123456789\n").unwrap();
+    }
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"@file { glob: "*.txt", fence: false, max_total_size: 30 }
+@answer { provider: { kind: mock, echo: true } }
+"#,
+    );
+
+    assert!(document.contains(
+        "\n`a.txt`:\n0123456789\n`b.txt`:\n0123456789\nSkipped 1 files:\n- `c.txt`: total size limit reached\n"
+    ));
+}
@@ -0,0 +1,96 @@
+use super::test_utils::{execute_document, run_context, write_context};
+use super::{ExecuteError, ExecuteOptions};
+
+#[test]
+fn test_foreach_items_answer_each() {
+    let dir = tempfile::tempdir().unwrap();
+    write_context(
+        dir.path(),
+        "summarize.md",
+        "Summarize the {{item}} module.\n@answer\n",
+    );
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"@set { provider: { kind: mock, replies: ["Parses tags.", "Runs tags."] } }
+@foreach { items: [parser, engine] } summarize.md
+"#,
+    );
+
+    assert!(document.contains("\nSummarize the parser module.\n"));
+    assert!(document.contains("\nParses tags.\n"));
+    assert!(document.contains("\nSummarize the engine module.\n"));
+    assert!(document.contains("\nRuns tags.\n"));
+    // One inline anchor and one answer anchor per item
+    assert_eq!(document.matches("<!-- inline-").count(), 4);
+    assert_eq!(document.matches("<!-- answer-").count(), 4);
+}
+
+#[test]
+fn test_foreach_glob() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("docs")).unwrap();
+    for name in ["docs/b.md", "docs/a.md", "docs/notes.txt"] {
+        std::fs::write(dir.path().join(name), "content").unwrap();
+    }
+    write_context(
+        dir.path(),
+        "review.md",
+        "Review {{item}} ({{index}} of {{count}}).\n",
+    );
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        "@foreach { glob: \"docs/*.md\" } review.md\n",
+    );
+
+    let a = document.find("\nReview docs/a.md (1 of 2).\n").unwrap();
+    let b = document.find("\nReview docs/b.md (2 of 2).\n").unwrap();
+    assert!(a < b);
+    assert!(!document.contains("notes.txt"));
+}
+
+#[test]
+fn test_foreach_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("questions.txt"),
+        "What is \"vespe\"?\n\nWhy C:\\temp?\n",
+    )
+    .unwrap();
+    // Triple braces keep the quotes unescaped
+    write_context(dir.path(), "ask.md", "Question: {{{item}}}\n");
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        "@foreach { lines: questions.txt } ask.md\n",
+    );
+
+    assert!(document.contains("\nQuestion: What is \"vespe\"?\n"));
+    assert!(document.contains("\nQuestion: Why C:\\temp?\n"));
+    assert_eq!(document.matches("<!-- inline-").count(), 4);
+}
+
+#[test]
+fn test_foreach_outside_project() {
+    let dir = tempfile::tempdir().unwrap();
+    write_context(dir.path(), "ask.md", "Question: {{{item}}}\n");
+    for source in [
+        "lines: \"../x\"",
+        "lines: \"/etc/passwd\"",
+        "glob: \"../*\"",
+    ] {
+        write_context(
+            dir.path(),
+            "main.md",
+            &format!("@foreach {{ {} }} ask.md\n", source),
+        );
+        let result = run_context(dir.path(), "main.md", ExecuteOptions::default());
+        assert!(
+            matches!(result, Err(ExecuteError::FileOutsideProject { .. })),
+            "{}: {:?}",
+            source,
+            result
+        );
+    }
+}
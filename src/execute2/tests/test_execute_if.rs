@@ -0,0 +1,151 @@
+use super::test_utils::{execute_document, run_context, run_context_with_data, write_context};
+use super::{ExecuteError, ExecuteOptions};
+use crate::ast2::{JsonPlusEntity, JsonPlusObject};
+
+#[test]
+fn test_if_on_define() {
+    let dir = tempfile::tempdir().unwrap();
+    let context_path = write_context(
+        dir.path(),
+        "main.md",
+        r#"@set { provider: { kind: mock, echo: true } }
+@if { var: mode, equals: review }
+Review the draft carefully.
+@else
+Write a first draft.
+@endif
+@answer
+"#,
+    );
+    let mut data = JsonPlusObject::new();
+    data.insert(
+        "$mode".to_string(),
+        JsonPlusEntity::NudeString("review".to_string()),
+    );
+
+    run_context_with_data(
+        dir.path(),
+        "main.md",
+        Some(&data),
+        ExecuteOptions::default(),
+    )
+    .unwrap();
+
+    // The answer echoes its query, which holds only the branch taken
+    let document = std::fs::read_to_string(&context_path).unwrap();
+    assert_eq!(document.matches("Review the draft carefully.").count(), 2);
+    assert_eq!(document.matches("Write a first draft.").count(), 1);
+}
+
+#[test]
+fn test_if_on_define_in_included_context() {
+    let dir = tempfile::tempdir().unwrap();
+    write_context(
+        dir.path(),
+        "part.md",
+        r#"@if { var: mode, equals: review }
+Review the draft carefully.
+@else
+Write a first draft.
+@endif
+@if { var: topic, equals: rust }
+Focus on {{topic}}.
+@endif
+"#,
+    );
+    let context_path = write_context(
+        dir.path(),
+        "main.md",
+        r#"@set { provider: { kind: mock, echo: true } }
+@include { data: { topic: rust } } part.md
+@answer
+"#,
+    );
+    let mut data = JsonPlusObject::new();
+    data.insert(
+        "$mode".to_string(),
+        JsonPlusEntity::NudeString("review".to_string()),
+    );
+
+    run_context_with_data(
+        dir.path(),
+        "main.md",
+        Some(&data),
+        ExecuteOptions::default(),
+    )
+    .unwrap();
+
+    // The included context sees both the defines of the execution and its own data
+    let document = std::fs::read_to_string(&context_path).unwrap();
+    assert!(document.contains("Review the draft carefully."));
+    assert!(!document.contains("Write a first draft."));
+    assert!(document.contains("Focus on rust."));
+}
+
+#[test]
+fn test_if_on_stored_choice() {
+    let dir = tempfile::tempdir().unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"@set { provider: { kind: mock, replies: ["§fix"], echo: true } }
+Is this a bug or a feature request?
+@answer { choose: { fix: fix, feature: feature }, store: kind }
+@if { var: kind, equals: [fix, hotfix] }
+Plan the fix.
+@answer
+@else
+Plan the feature.
+@answer
+@endif
+"#,
+    );
+
+    assert_eq!(document.matches("Plan the fix.").count(), 2);
+    assert_eq!(document.matches("Plan the feature.").count(), 1);
+    // The answer in the branch not taken is not executed
+    assert!(document.contains("\nPlan the feature.\n@answer\n@endif\n"));
+}
+
+#[test]
+fn test_if_nested_and_negated() {
+    let dir = tempfile::tempdir().unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"@set { provider: { kind: mock, echo: true } }
+@if { var: missing, not: true }
+Outer taken.
+@if { var: missing }
+Inner taken.
+@else
+Inner alternative.
+@endif
+@else
+Outer alternative.
+@if { var: missing, not: true }
+Nested in alternative.
+@endif
+@endif
+@answer
+"#,
+    );
+
+    assert_eq!(document.matches("Outer taken.").count(), 2);
+    assert_eq!(document.matches("Inner alternative.").count(), 2);
+    assert_eq!(document.matches("Inner taken.").count(), 1);
+    assert_eq!(document.matches("Outer alternative.").count(), 1);
+    assert_eq!(document.matches("Nested in alternative.").count(), 1);
+}
+
+#[test]
+fn test_if_unbalanced() {
+    let dir = tempfile::tempdir().unwrap();
+    write_context(dir.path(), "unterminated.md", "@if { var: mode }\nText\n");
+    write_context(dir.path(), "unmatched.md", "Text\n@endif\n");
+
+    let result = run_context(dir.path(), "unterminated.md", ExecuteOptions::default());
+    assert!(matches!(result, Err(ExecuteError::UnterminatedIf { .. })));
+    let result = run_context(dir.path(), "unmatched.md", ExecuteOptions::default());
+    assert!(matches!(result, Err(ExecuteError::UnmatchedEndIf { .. })));
+}
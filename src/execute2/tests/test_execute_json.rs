@@ -0,0 +1,74 @@
+use super::test_utils::{answer_state, run_context, write_context};
+use super::ExecuteOptions;
+
+const SCHEMA: &str = r#"{
+  "type": "object",
+  "properties": { "port": { "type": "integer" } },
+  "required": ["port"]
+}"#;
+
+#[test]
+fn test_json_output_extracted_and_validated() {
+    let dir = tempfile::tempdir().unwrap();
+    write_context(dir.path(), "schema.json", SCHEMA);
+    let context_path = write_context(
+        dir.path(),
+        "main.md",
+        r#"Which port should the server use?
+@answer { provider: { kind: mock, replies: ['Sure! {"port": 8080} is fine.'] }, schema: "schema.json" }
+"#,
+    );
+
+    run_context(dir.path(), "main.md", ExecuteOptions::default()).unwrap();
+
+    let document = std::fs::read_to_string(&context_path).unwrap();
+    assert!(document.contains("\n{\n  \"port\": 8080\n}\n"));
+    let state = answer_state(dir.path(), "main.md");
+    assert_eq!(state.json, Some(serde_json::json!({ "port": 8080 })));
+    assert!(state.query.contains("\"required\""));
+    assert!(state.format_errors.is_empty());
+}
+
+#[test]
+fn test_json_output_retries_invalid_replies() {
+    let dir = tempfile::tempdir().unwrap();
+    write_context(dir.path(), "schema.json", SCHEMA);
+    write_context(
+        dir.path(),
+        "main.md",
+        r#"Which port should the server use?
+@answer { provider: { kind: mock, replies: ["Port 8080.", '{"port": "8080"}', '```json
+[1]
+```', '{"port": 8080}'] }, schema: "schema.json", format_retries: 3 }
+"#,
+    );
+
+    run_context(dir.path(), "main.md", ExecuteOptions::default()).unwrap();
+
+    let state = answer_state(dir.path(), "main.md");
+    assert_eq!(state.json, Some(serde_json::json!({ "port": 8080 })));
+    assert_eq!(state.format_errors.len(), 3);
+    assert!(state.format_errors[0].contains("no JSON value"));
+    assert!(state.format_errors[1].contains("/port"));
+    // The latest query tells the model why the previous reply was rejected
+    assert!(state.query.contains(&state.format_errors[2]));
+}
+
+#[test]
+fn test_json_output_gives_up() {
+    let dir = tempfile::tempdir().unwrap();
+    write_context(
+        dir.path(),
+        "main.md",
+        r#"Which port should the server use?
+@answer { provider: { kind: mock, replies: ["Port 8080.", "Port 8081."] }, format: json, format_retries: 1 }
+"#,
+    );
+
+    run_context(dir.path(), "main.md", ExecuteOptions::default()).unwrap();
+
+    let state = answer_state(dir.path(), "main.md");
+    assert_eq!(state.status.to_string(), "interrupted");
+    assert_eq!(state.json, None);
+    assert_eq!(state.format_errors.len(), 2);
+}
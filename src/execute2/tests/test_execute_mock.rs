@@ -0,0 +1,170 @@
+use super::test_utils::{execute_document, run_context, write_context};
+use super::{execute_context_with_options, ExecuteError, ExecuteOptions};
+use crate::ast2::{JsonPlusEntity, JsonPlusObject};
+use crate::config::ProviderProfile;
+use crate::utils::file::ProjectFileAccessor;
+use crate::utils::path::ProjectPathResolver;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[test]
+fn test_execute_context_with_mock_replies() {
+    let dir = tempfile::tempdir().unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"@set { provider: { kind: mock, replies: ["First reply.", "Second reply."] } }
+First question?
+@answer
+Second question?
+@answer
+"#,
+    );
+
+    let first = document
+        .find("\nFirst reply.\n")
+        .expect("first reply injected");
+    let second = document
+        .find("\nSecond reply.\n")
+        .expect("second reply injected");
+    let second_question = document.find("Second question?").unwrap();
+    assert!(first < second_question && second_question < second);
+    assert!(!document.contains("\n@answer\n"));
+}
+
+#[test]
+fn test_execute_context_with_mock_script_in_project() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("scripts")).unwrap();
+    std::fs::write(
+        dir.path().join("scripts/replies.json"),
+        r#"["Reply from the project script."]"#,
+    )
+    .unwrap();
+    // The script is relative to the project root, not to the working directory
+    assert!(!std::path::Path::new("scripts/replies.json").exists());
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"Question?
+@answer { provider: "mock:scripts/replies.json" }
+"#,
+    );
+
+    assert!(document.contains("\nReply from the project script.\n"));
+}
+
+#[test]
+fn test_execute_context_with_mock_rules() {
+    let dir = tempfile::tempdir().unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"What is the capital of France?
+@answer { provider: { kind: mock, replies: [{ match: "capital of France", reply: "Paris." }] } }
+"#,
+    );
+
+    assert!(document.contains("Paris."));
+}
+
+fn profile_options() -> ExecuteOptions {
+    let profile = |provider: serde_json::Value| ProviderProfile {
+        provider,
+        parameters: BTreeMap::new(),
+    };
+    ExecuteOptions {
+        provider_profiles: BTreeMap::from([
+            (
+                "fast".to_string(),
+                profile(json!({ "kind": "mock", "replies": ["From profile."] })),
+            ),
+            (
+                "broken".to_string(),
+                profile(json!({ "kind": "mock", "replies": [] })),
+            ),
+        ]),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_execute_context_with_provider_profile() {
+    let dir = tempfile::tempdir().unwrap();
+    let context_path = write_context(
+        dir.path(),
+        "main.md",
+        "Question?\n@answer { provider: \"@fast\" }\n",
+    );
+
+    run_context(dir.path(), "main.md", profile_options()).unwrap();
+
+    let document = std::fs::read_to_string(context_path).unwrap();
+    assert!(document.contains("\nFrom profile.\n"));
+}
+
+#[test]
+fn test_execute_context_with_provider_profile_chain() {
+    let dir = tempfile::tempdir().unwrap();
+    let context_path = write_context(
+        dir.path(),
+        "main.md",
+        "Question?\n@answer { provider: [\"@broken\", \"@fast\"] }\n",
+    );
+
+    run_context(dir.path(), "main.md", profile_options()).unwrap();
+
+    let document = std::fs::read_to_string(context_path).unwrap();
+    assert!(document.contains("\nFrom profile.\n"));
+}
+
+#[test]
+fn test_execute_context_with_unknown_provider_profile() {
+    let dir = tempfile::tempdir().unwrap();
+    write_context(
+        dir.path(),
+        "main.md",
+        "Question?\n@answer { provider: \"@missing\" }\n",
+    );
+
+    let result = run_context(dir.path(), "main.md", profile_options());
+
+    assert!(matches!(
+        result,
+        Err(ExecuteError::UnsupportedParameterValue(_))
+    ));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_execute_context_keeps_prompt_placeholders() {
+    let dir = tempfile::tempdir().unwrap();
+    let context_path = write_context(
+        dir.path(),
+        "main.md",
+        "Question {{$1}}?\n@answer { provider: \"cat {{prompt_file}}\" }\n",
+    );
+    let mut data = JsonPlusObject::new();
+    data.insert(
+        "$1".to_string(),
+        JsonPlusEntity::NudeString("one".to_string()),
+    );
+
+    execute_context_with_options(
+        Arc::new(ProjectFileAccessor::new(dir.path(), None)),
+        Arc::new(ProjectPathResolver::new(
+            dir.path().to_path_buf(),
+            Vec::new(),
+            None,
+        )),
+        "main.md",
+        Some(&data),
+        ExecuteOptions::default(),
+    )
+    .unwrap();
+
+    let document = std::fs::read_to_string(context_path).unwrap();
+    assert!(document.contains("cat {{prompt_file}}"));
+    assert_eq!(document.matches("Question one?").count(), 2);
+}
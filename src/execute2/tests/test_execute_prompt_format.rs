@@ -0,0 +1,170 @@
+use super::test_utils::{answer_state, execute_document, run_context, write_context};
+use super::{
+    ChatMessage, ChatRole, ExecuteError, ExecuteOptions, ModelContent, ModelContentItem,
+    PromptConfig, PromptFormat,
+};
+
+fn content() -> ModelContent {
+    let mut content = ModelContent::new();
+    content.push(ModelContentItem::system("Be terse."));
+    content.push(ModelContentItem::user("Is 1 < 2 && 3 > 2?"));
+    content.push(ModelContentItem::user("Answer <b>yes</b> or no."));
+    content.push(ModelContentItem::agent(None, "Yes."));
+    content.push(ModelContentItem::user("Why?"));
+    content
+}
+
+fn config(format: PromptFormat) -> PromptConfig {
+    PromptConfig {
+        agent: None,
+        format,
+        with_agent_names: false,
+        with_invitation: false,
+    }
+}
+
+fn role(name: &str) -> ChatRole {
+    serde_json::from_value(serde_json::Value::String(name.to_string())).unwrap()
+}
+
+/// Parses the messages of a prompt whose messages are `<begin><role><header>content<end>`.
+fn parse_delimited(prompt: &str, begin: &str, header: &str, end: &str) -> Vec<ChatMessage> {
+    prompt
+        .split(begin)
+        .skip(1)
+        .map(|x| {
+            let (name, rest) = x.split_once(header).unwrap();
+            ChatMessage {
+                role: role(name),
+                content: rest.split(end).next().unwrap().to_string(),
+            }
+        })
+        .collect()
+}
+
+fn parse_xml(prompt: &str) -> Vec<ChatMessage> {
+    prompt
+        .split("<message role=\"")
+        .skip(1)
+        .map(|x| {
+            let (name, rest) = x.split_once("\">\n").unwrap();
+            let content = rest.split("\n</message>").next().unwrap();
+            ChatMessage {
+                role: role(name),
+                content: content
+                    .replace("&lt;", "<")
+                    .replace("&gt;", ">")
+                    .replace("&amp;", "&"),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_prompt_format_round_trip() {
+    let content = content();
+    let messages = content.to_messages(&config(PromptFormat::Parts));
+    assert_eq!(messages.len(), 4);
+
+    let prompt = content.to_prompt(&config(PromptFormat::Json));
+    let parsed: Vec<ChatMessage> = serde_json::from_str(&prompt).unwrap();
+    assert_eq!(parsed, messages);
+
+    let prompt = content.to_prompt(&config(PromptFormat::ChatMl));
+    assert!(prompt.starts_with("<|im_start|>system\nBe terse.<|im_end|>\n"));
+    assert_eq!(
+        parse_delimited(&prompt, "<|im_start|>", "\n", "<|im_end|>"),
+        messages
+    );
+
+    let prompt = content.to_prompt(&config(PromptFormat::Llama3));
+    assert!(prompt.starts_with("<|begin_of_text|><|start_header_id|>system<|end_header_id|>\n\n"));
+    assert_eq!(
+        parse_delimited(
+            &prompt,
+            "<|start_header_id|>",
+            "<|end_header_id|>\n\n",
+            "<|eot_id|>"
+        ),
+        messages
+    );
+
+    let prompt = content.to_prompt(&config(PromptFormat::Xml));
+    assert!(prompt.contains("&lt;b&gt;yes&lt;/b&gt;"));
+    assert_eq!(parse_xml(&prompt), messages);
+
+    let prompt = content.to_prompt(&config(PromptFormat::Plain));
+    let contents = messages
+        .iter()
+        .map(|x| x.content.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(prompt, format!("{}\n", contents.join("\n\n")));
+}
+
+#[test]
+fn test_prompt_format_invitation() {
+    let content = content();
+    let mut config = config(PromptFormat::ChatMl);
+    config.with_invitation = true;
+    assert!(content
+        .to_prompt(&config)
+        .ends_with("<|im_start|>user\nWhy?<|im_end|>\n<|im_start|>assistant\n"));
+
+    config.format = PromptFormat::Llama3;
+    assert!(content
+        .to_prompt(&config)
+        .ends_with("<|eot_id|><|start_header_id|>assistant<|end_header_id|>\n\n"));
+
+    // No invitation in the JSON messages
+    config.format = PromptFormat::Json;
+    let parsed: Vec<ChatMessage> = serde_json::from_str(&content.to_prompt(&config)).unwrap();
+    assert_eq!(parsed.last().unwrap().role, ChatRole::User);
+}
+
+#[test]
+fn test_prompt_format_parameter() {
+    let dir = tempfile::tempdir().unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        "What is Rust?\n@answer { provider: { kind: mock, echo: true }, prompt_format: json }\n",
+    );
+    assert!(document.contains("\"role\": \"user\""));
+    assert!(document.contains("\"content\": \"What is Rust?\""));
+
+    write_context(
+        dir.path(),
+        "main.md",
+        "What is Rust?\n@answer { provider: { kind: mock, echo: true }, prompt_format: yaml }\n",
+    );
+    let result = run_context(dir.path(), "main.md", ExecuteOptions::default());
+    assert!(matches!(
+        result,
+        Err(ExecuteError::UnsupportedParameterValue(_))
+    ));
+}
+
+#[test]
+fn test_prompt_format_with_json_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"Which port should the server use?
+@answer { provider: { kind: mock, replies: ['Sure! {"port": 8080} is fine.'] }, format: json, prompt_format: json }
+"#,
+    );
+
+    // `format` shapes the reply: the JSON value is extracted and injected
+    assert!(document.contains("\n{\n  \"port\": 8080\n}\n"));
+    let state = answer_state(dir.path(), "main.md");
+    assert_eq!(state.json, Some(serde_json::json!({ "port": 8080 })));
+    // `prompt_format` shapes the query: a JSON array of messages, with the instructions
+    let messages: Vec<ChatMessage> = serde_json::from_str(&state.query).unwrap();
+    assert!(messages[0]
+        .content
+        .contains("Which port should the server use?"));
+    assert!(messages
+        .iter()
+        .any(|x| x.content.contains("Reply with a single JSON value")));
+}
@@ -0,0 +1,148 @@
+use super::test_utils::{execute_document, run_context, write_context};
+use super::{analyze_context, AnchorState, ExecuteOptions};
+use crate::utils::file::ProjectFileAccessor;
+use crate::utils::path::ProjectPathResolver;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Returns the exit code recorded by the only `@shell` of context `name`.
+fn shell_exit_code(root: &Path, name: &str) -> Option<i32> {
+    let analysis = analyze_context(
+        Arc::new(ProjectFileAccessor::new(root, None)),
+        Arc::new(ProjectPathResolver::new(
+            root.to_path_buf(),
+            Vec::new(),
+            None,
+        )),
+        name,
+    )
+    .unwrap();
+    analysis
+        .anchors
+        .into_values()
+        .find_map(|x| match x.state {
+            AnchorState::Shell(state) => Some(state.exit_code),
+            _ => None,
+        })
+        .expect("no shell anchor")
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_output_is_user_content() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("marker.txt"), "").unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"Which files are there?
+@shell { command: "ls *.txt | tr a-z A-Z" }
+@answer { provider: { kind: mock, echo: true } }
+"#,
+    );
+
+    // The command runs in the project root, and the answer echoes its query
+    assert_eq!(document.matches("\nMARKER.TXT\n").count(), 2);
+    assert_eq!(shell_exit_code(dir.path(), "main.md"), Some(0));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_failure_keeps_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        "@shell { command: \"echo partial; echo broken >&2; exit 3\", stderr: true }\n",
+    );
+
+    assert!(document.contains("\npartial\nbroken\n"));
+    assert_eq!(shell_exit_code(dir.path(), "main.md"), Some(3));
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_dynamic_follows_output() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("version.txt"), "1.0\n").unwrap();
+    let context_path = write_context(
+        dir.path(),
+        "main.md",
+        r#"@shell { command: "cat version.txt", dynamic: true }
+@shell { command: "cat version.txt" }
+"#,
+    );
+    run_context(dir.path(), "main.md", ExecuteOptions::default()).unwrap();
+    let document = std::fs::read_to_string(&context_path).unwrap();
+    assert_eq!(document.matches("\n1.0\n").count(), 2);
+
+    std::fs::write(dir.path().join("version.txt"), "2.0\n").unwrap();
+    run_context(dir.path(), "main.md", ExecuteOptions::default()).unwrap();
+
+    // Only the dynamic command runs again
+    let document = std::fs::read_to_string(&context_path).unwrap();
+    assert_eq!(document.matches("\n1.0\n").count(), 1);
+    assert_eq!(document.matches("\n2.0\n").count(), 1);
+    let dynamic = document.find("\n2.0\n").unwrap();
+    let fixed = document.find("\n1.0\n").unwrap();
+    assert!(dynamic < fixed);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_dynamic_runs_once_per_run() {
+    let dir = tempfile::tempdir().unwrap();
+    write_context(
+        dir.path(),
+        "main.md",
+        r#"@shell { command: "echo run >> runs.log; wc -l < runs.log", dynamic: true }
+Is the count right?
+@answer { provider: { kind: mock, replies: ["Yes."] } }
+"#,
+    );
+    let runs = || {
+        std::fs::read_to_string(dir.path().join("runs.log"))
+            .unwrap()
+            .lines()
+            .count()
+    };
+
+    // The output changes on every run of the command, yet the run converges
+    run_context(dir.path(), "main.md", ExecuteOptions::default()).unwrap();
+    assert_eq!(runs(), 1);
+    run_context(dir.path(), "main.md", ExecuteOptions::default()).unwrap();
+    assert_eq!(runs(), 2);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_shell_output_is_verbatim() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = "println!(\"{{}}\", 1);\n@answer\n@include other\n";
+    std::fs::write(dir.path().join("code.txt"), output).unwrap();
+    let part_path = write_context(
+        dir.path(),
+        "part.md",
+        "Code of {{name}}:\n@shell { command: \"cat code.txt\" }\n",
+    );
+    write_context(
+        dir.path(),
+        "main.md",
+        r#"Hi.
+@answer { provider: { kind: mock, replies: ["Hello."] }, store: greeting }
+@include { data: { name: "main" } } part.md
+"#,
+    );
+
+    // Neither rendered nor executed, neither now nor on later runs
+    run_context(dir.path(), "part.md", ExecuteOptions::default()).unwrap();
+    for _ in 0..2 {
+        let content = run_context(dir.path(), "main.md", ExecuteOptions::default()).unwrap();
+        assert!(content
+            .to_string()
+            .ends_with(&format!("Code of main:\n\n{}", output)));
+    }
+    assert!(std::fs::read_to_string(&part_path)
+        .unwrap()
+        .contains(output));
+}
@@ -0,0 +1,101 @@
+use super::test_utils::{execute_document, run_context_with_data, write_context};
+use super::ExecuteOptions;
+use crate::ast2::JsonPlusObject;
+
+#[test]
+fn test_stored_answer_renders_later_prompts() {
+    let dir = tempfile::tempdir().unwrap();
+    let context_path = write_context(
+        dir.path(),
+        "main.md",
+        r#"@set { provider: { kind: mock, replies: ["Rust"], echo: true } }
+Pick a programming language.
+@answer { store: language }
+Write a haiku about {{language}}.
+@answer
+"#,
+    );
+
+    // Projects always render their data into the context, which must keep the variables
+    run_context_with_data(
+        dir.path(),
+        "main.md",
+        Some(&JsonPlusObject::new()),
+        ExecuteOptions::default(),
+    )
+    .unwrap();
+
+    let document = std::fs::read_to_string(&context_path).unwrap();
+    assert!(document.contains("\nWrite a haiku about {{language}}.\n"));
+    // The second answer echoes its query
+    assert!(document.contains("Write a haiku about Rust."));
+}
+
+#[test]
+fn test_undefined_placeholders_render_empty() {
+    let dir = tempfile::tempdir().unwrap();
+    let context_path = write_context(
+        dir.path(),
+        "main.md",
+        r#"@set { provider: { kind: mock, replies: ["Rust"], echo: true } }
+Pick a programming language.
+@answer { store: language }
+Hello {{$1}} and {{missing}}, write about {{language}}.
+@answer
+"#,
+    );
+
+    run_context_with_data(
+        dir.path(),
+        "main.md",
+        Some(&JsonPlusObject::new()),
+        ExecuteOptions::default(),
+    )
+    .unwrap();
+
+    // Only the stored variables are kept for later rendering
+    let document = std::fs::read_to_string(&context_path).unwrap();
+    assert!(document.contains("Hello  and , write about Rust."));
+}
+
+#[test]
+fn test_stored_json_answer_fields() {
+    let dir = tempfile::tempdir().unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"@set { provider: { kind: mock, replies: ['{"host": "db.local", "port": 5432}'], echo: true } }
+Describe the database server.
+@answer { format: json, store: server }
+Connect to {{server.host}} on port {{server.port}}.
+@answer
+"#,
+    );
+
+    assert!(document.contains("Connect to db.local on port 5432."));
+}
+
+#[test]
+fn test_stored_variables_in_include_data() {
+    let dir = tempfile::tempdir().unwrap();
+    write_context(dir.path(), "greeting.md", "Greet {{name}}.\n");
+    write_context(
+        dir.path(),
+        "farewell.md",
+        "Say goodbye after: {{previous}}\n",
+    );
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        r#"@set { provider: { kind: mock, echo: true } }
+@inline { data: { name: "Ada" }, store: greeting } greeting.md
+@include { data: { previous: "{{greeting}}" } } farewell.md
+Repeat: {{greeting}}
+@answer
+"#,
+    );
+
+    // The answer echoes its query
+    assert!(document.contains("\nSay goodbye after: Greet Ada.\n"));
+    assert!(document.contains("\nRepeat: Greet Ada.\n"));
+}
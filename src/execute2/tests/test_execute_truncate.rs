@@ -0,0 +1,112 @@
+use super::test_utils::{execute_document, run_context, write_context};
+use super::{ExecuteError, ExecuteOptions};
+
+#[test]
+fn test_truncate_oldest_keeps_system_prefix() {
+    let dir = tempfile::tempdir().unwrap();
+    write_context(dir.path(), "system.md", "Be terse.\n");
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        &format!(
+            r#"@set {{ provider: {{ kind: mock, replies: ["Reply one.", "Reply two."] }} }}
+First question, {}
+@answer
+Second question.
+@answer
+Third question.
+@answer {{ provider: {{ kind: mock, echo: true }}, prefix: system.md, max_context: {{ chars: 140 }} }}
+"#,
+            "padding ".repeat(20)
+        ),
+    );
+
+    assert!(document.contains(
+        r#"---
+System:
+Be terse.
+
+---
+User:
+[... 1 message removed to fit the context window ...]
+
+Assistant:
+Reply one.
+
+---
+User:
+Second question.
+
+Assistant:
+Reply two.
+
+---
+User:
+Third question.
+"#
+    ));
+}
+
+#[test]
+fn test_truncate_middle_out() {
+    let dir = tempfile::tempdir().unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        &format!(
+            r#"@set {{ provider: {{ kind: mock, replies: ["Reply one.", "Reply two."] }} }}
+First question.
+@answer
+Second question, {}
+@answer
+Third question.
+@answer {{ provider: {{ kind: mock, echo: true }}, max_context: {{ chars: 120 }}, truncate: middle }}
+"#,
+            "padding ".repeat(20)
+        ),
+    );
+
+    assert!(document.contains(
+        r#"---
+User:
+First question.
+
+Assistant:
+Reply one.
+
+---
+User:
+[... 1 message removed to fit the context window ...]
+
+Assistant:
+Reply two.
+
+---
+User:
+Third question.
+"#
+    ));
+}
+
+#[test]
+fn test_truncate_fitting_or_invalid() {
+    let dir = tempfile::tempdir().unwrap();
+    let document = execute_document(
+        dir.path(),
+        "main.md",
+        "Short question.\n@answer { provider: { kind: mock, echo: true }, max_context: 1000 }\n",
+    );
+    assert!(document.contains("User:\nShort question.\n"));
+    assert!(!document.contains("removed to fit"));
+
+    write_context(
+        dir.path(),
+        "main.md",
+        "Question.\n@answer { provider: { kind: mock, echo: true }, max_context: { lines: 3 } }\n",
+    );
+    let result = run_context(dir.path(), "main.md", ExecuteOptions::default());
+    assert!(matches!(
+        result,
+        Err(ExecuteError::UnsupportedParameterValue(_))
+    ));
+}
@@ -0,0 +1,40 @@
+use super::test_utils::{run_context, write_context};
+use super::{collect_usage, ExecuteOptions};
+use crate::agent::usage::UsageTally;
+use crate::constants::{CTX_DIR_NAME, METADATA_DIR_NAME};
+use std::sync::Arc;
+
+#[test]
+fn test_usage_recorded_per_answer_and_run() {
+    let dir = tempfile::tempdir().unwrap();
+    write_context(
+        dir.path(),
+        "main.md",
+        r#"@set { provider: { kind: mock, replies: ["Paris.", "Rome."] }, pricing: { input: 1000000, output: 2000000 } }
+What is the capital of France?
+@answer
+And of Italy?
+@answer
+"#,
+    );
+    let tally = Arc::new(UsageTally::default());
+    let options = ExecuteOptions {
+        usage: Some(tally.clone()),
+        ..Default::default()
+    };
+
+    run_context(dir.path(), "main.md", options).unwrap();
+
+    let run = tally.summary();
+    assert_eq!(run.total.answers, 2);
+    assert!(run.total.usage.input_tokens > 0);
+    assert_eq!(run.by_provider["mock"].answers, 2);
+    // Replies get a trailing newline: "Paris.\n" and "Rome.\n" are 2 tokens each
+    assert_eq!(run.total.usage.output_tokens, 4);
+    let expected_cost =
+        run.total.usage.input_tokens as f64 + 2.0 * run.total.usage.output_tokens as f64;
+    assert!((run.total.usage.cost.unwrap() - expected_cost).abs() < 1e-9);
+
+    let project = collect_usage(&dir.path().join(CTX_DIR_NAME).join(METADATA_DIR_NAME)).unwrap();
+    assert_eq!(project, run);
+}
@@ -0,0 +1,77 @@
+use super::tag_answer::AnswerState;
+use super::{
+    analyze_context, execute_context_with_options, AnchorState, ExecuteOptions, ModelContent,
+    Result,
+};
+use crate::ast2::JsonPlusObject;
+use crate::utils::file::ProjectFileAccessor;
+use crate::utils::path::ProjectPathResolver;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Writes `content` as context `name` in the project at `root`, returning its path.
+pub fn write_context(root: &Path, name: &str, content: &str) -> PathBuf {
+    let path_res = ProjectPathResolver::new(root.to_path_buf(), Vec::new(), None);
+    let context_path = path_res.contexts_root().join(name);
+    std::fs::create_dir_all(context_path.parent().unwrap()).unwrap();
+    std::fs::write(&context_path, content).unwrap();
+    context_path
+}
+
+/// Executes context `name` of the project at `root`.
+pub fn run_context(root: &Path, name: &str, options: ExecuteOptions) -> Result<ModelContent> {
+    run_context_with_data(root, name, None, options)
+}
+
+/// Executes context `name` of the project at `root`, rendering `data` into it.
+pub fn run_context_with_data(
+    root: &Path,
+    name: &str,
+    data: Option<&JsonPlusObject>,
+    options: ExecuteOptions,
+) -> Result<ModelContent> {
+    execute_context_with_options(
+        Arc::new(ProjectFileAccessor::new(root, None)),
+        Arc::new(ProjectPathResolver::new(
+            root.to_path_buf(),
+            Vec::new(),
+            None,
+        )),
+        name,
+        data,
+        options,
+    )
+}
+
+/// Writes `content` as context `name` in the project at `root`, executes it and
+/// returns the resulting document.
+pub fn execute_document(root: &Path, name: &str, content: &str) -> String {
+    let context_path = write_context(root, name, content);
+    if let Err(e) = run_context(root, name, ExecuteOptions::default()) {
+        panic!(
+            "{:?}\n{}",
+            e,
+            std::fs::read_to_string(&context_path).unwrap()
+        );
+    }
+    std::fs::read_to_string(&context_path).unwrap()
+}
+
+/// Returns the state of the only `@answer` of context `name` of the project at `root`.
+pub fn answer_state(root: &Path, name: &str) -> Box<AnswerState> {
+    let analysis = analyze_context(
+        Arc::new(ProjectFileAccessor::new(root, None)),
+        Arc::new(ProjectPathResolver::new(
+            root.to_path_buf(),
+            Vec::new(),
+            None,
+        )),
+        name,
+    )
+    .unwrap();
+    let analysis = analysis.anchors.into_values().next().unwrap();
+    match analysis.state {
+        AnchorState::Answer(state) => state,
+        x => panic!("not an answer: {:?}", x),
+    }
+}
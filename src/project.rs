@@ -1,6 +1,9 @@
+use crate::agent::cache::{ResponseCache, CACHE_DIR_NAME};
+use crate::agent::cassette::{Cassette, CassetteMode, CASSETTE_FILE_NAME};
+use crate::agent::usage::{UsageSummary, UsageTally};
 use crate::ast2::{JsonPlusEntity, JsonPlusObject};
 use crate::constants::{CTX_DIR_NAME, CTX_ROOT_FILE_NAME, METADATA_DIR_NAME};
-use crate::execute2::{ContextAnalysis, ModelContent};
+use crate::execute2::{ContextAnalysis, ExecuteOptions, ModelContent};
 use crate::utils::file::{FileAccessor, ProjectFileAccessor};
 use crate::utils::path::{PathResolver, ProjectPathResolver};
 
@@ -14,7 +17,7 @@ use std::collections::BTreeMap;
 use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 
-use crate::config::{EditorInterface, ProjectConfig};
+use crate::config::{EditorInterface, ProjectConfig, ProviderProfile};
 use crate::editor::{lockfile::FileBasedEditorCommunicator, EditorCommunicator};
 
 pub struct Project {
@@ -31,6 +34,8 @@ pub struct ExecuteContextInput {
     pub defines: Option<Vec<String>>,
     pub additional_aux_paths: Option<Vec<PathBuf>>,
     pub output_path: Option<PathBuf>,
+    pub cassette: Option<CassetteMode>,
+    pub cache: bool,
 }
 
 impl Default for ExecuteContextInput {
@@ -42,6 +47,8 @@ impl Default for ExecuteContextInput {
             defines: None,
             additional_aux_paths: None,
             output_path: None,
+            cassette: None,
+            cache: false,
         }
     }
 }
@@ -190,12 +197,53 @@ impl Project {
 
         let path_res = path_res_builder;
 
-        let content = crate::execute2::execute_context(
+        let cassette = match input.cassette {
+            Some(mode) => Some(Arc::new(Cassette::open(&self.cassette_path(), mode)?)),
+            None => None,
+        };
+        let cache = match input.cache {
+            true => Some(Arc::new(self.response_cache())),
+            false => None,
+        };
+        let usage = Arc::new(UsageTally::default());
+        let options = ExecuteOptions {
+            cassette,
+            cache,
+            provider_profiles: self.project_config.providers.clone(),
+            tokenizer: None,
+            usage: Some(usage.clone()),
+            budget: self.project_config.budget.clone(),
+        };
+
+        let content = crate::execute2::execute_context_with_options(
             self.file_access.clone(),
             path_res,
             &input.context_name,
             Some(&data),
+            options,
         )?;
+        let pending = usage.pending();
+        if pending > 0 {
+            tracing::warn!(
+                "{} answers left pending: budget exhausted, run again to resume them.",
+                pending
+            );
+        }
+        let usage = usage.summary();
+        if usage.total.answers > 0 {
+            tracing::info!(
+                "Answered {} queries: ~{} input tokens, ~{} output tokens{}.",
+                usage.total.answers,
+                usage.total.usage.input_tokens,
+                usage.total.usage.output_tokens,
+                usage
+                    .total
+                    .usage
+                    .cost
+                    .map(|x| format!(", estimated cost {:.4}", x))
+                    .unwrap_or_default()
+            );
+        }
         self.commit(Some(format!("Executed context {}.", input.context_name)))?;
         Ok(content)
     }
@@ -209,6 +257,12 @@ impl Project {
         Ok(analysis)
     }
 
+    pub fn usage_summary(&self) -> Result<UsageSummary> {
+        Ok(crate::execute2::collect_usage(
+            &self.path_res.metadata_home(),
+        )?)
+    }
+
     pub fn project_home(&self) -> PathBuf {
         self.path_res.project_home()
     }
@@ -221,6 +275,18 @@ impl Project {
         self.path_res.metadata_home().join("project_config.json")
     }
 
+    pub fn cassette_path(&self) -> PathBuf {
+        self.path_res.metadata_home().join(CASSETTE_FILE_NAME)
+    }
+
+    pub fn cache_path(&self) -> PathBuf {
+        self.path_res.metadata_home().join(CACHE_DIR_NAME)
+    }
+
+    pub fn response_cache(&self) -> ResponseCache {
+        ResponseCache::open(&self.cache_path())
+    }
+
     pub fn create_context_file(
         &self,
         name: &str,
@@ -292,6 +358,25 @@ impl Project {
         &self.project_config.aux_paths
     }
 
+    pub fn add_provider_profile(&mut self, name: String, profile: ProviderProfile) -> Result<()> {
+        self.project_config.providers.insert(name, profile);
+        self.save_project_config()?;
+        self.commit(Some("Added provider profile to project config.".into()))?;
+        Ok(())
+    }
+
+    pub fn remove_provider_profile(&mut self, name: &str) -> Result<()> {
+        if self.project_config.providers.remove(name).is_some() {
+            self.save_project_config()?;
+            self.commit(Some("Removed provider profile from project config.".into()))?;
+        }
+        Ok(())
+    }
+
+    pub fn get_provider_profiles(&self) -> &BTreeMap<String, ProviderProfile> {
+        &self.project_config.providers
+    }
+
     pub fn commit(&self, title_message: Option<String>) -> Result<()> {
         if self.project_config.git_integration_enabled {
             Ok(self.file_access.commit(title_message)?)
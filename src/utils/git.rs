@@ -252,3 +252,42 @@ pub fn is_in_git_repository(root_path: &Path) -> Result<bool, Error> {
         }
     }
 }
+
+/// Returns the names among `file_names`, relative to `root_path`, that are ignored by git.
+/// None is ignored if `root_path` is not in a git repository.
+pub fn git_ignored_files(
+    root_path: &Path,
+    file_names: &[String],
+) -> Result<HashSet<String>, Error> {
+    let repo = match Repository::discover(root_path) {
+        Ok(repo) => repo,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(HashSet::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let workdir = repo.workdir().ok_or(Error::NoWorkdir)?;
+    let canonical_root = root_path
+        .canonicalize()
+        .map_err(|e| Error::CanonicalizePath {
+            path: root_path.to_path_buf(),
+            source: e,
+        })?;
+    let canonical_workdir = workdir
+        .canonicalize()
+        .map_err(|e| Error::CanonicalizePath {
+            path: workdir.to_path_buf(),
+            source: e,
+        })?;
+    let root_in_workdir = canonical_root
+        .strip_prefix(&canonical_workdir)
+        .map_err(|_| Error::PathOutsideWorkdir {
+            file_path: root_path.to_path_buf(),
+            workdir: workdir.to_path_buf(),
+        })?;
+    let mut ignored = HashSet::new();
+    for file_name in file_names {
+        if repo.is_path_ignored(root_in_workdir.join(file_name))? {
+            ignored.insert(file_name.clone());
+        }
+    }
+    Ok(ignored)
+}
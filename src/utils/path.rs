@@ -32,6 +32,8 @@ pub trait PathResolver: Send + Sync + Debug {
     fn resolve_output_file(&self, file_name: &str) -> Result<PathBuf>;
     /// Resolve a meta kind / uuid to a path, create directory if doesn't exist
     fn resolve_metadata(&self, meta_kind: &str, meta_uuid: &Uuid) -> Result<PathBuf>;
+    /// Root directory of the project, the parent of the project home
+    fn project_root(&self) -> PathBuf;
 }
 
 #[derive(Debug)]
@@ -131,4 +133,8 @@ impl PathResolver for ProjectPathResolver {
         })?;
         Ok(metadata_dir)
     }
+    /// Root directory of the project, the parent of the project home
+    fn project_root(&self) -> PathBuf {
+        self.root_path.clone()
+    }
 }